@@ -0,0 +1,513 @@
+//! Static analysis of roms, for reverse engineering them.
+//!
+//! Everything reachable from [`ORIGIN`] is walked, following jumps, calls, returns and skips.
+//! `BNNN` can go anywhere, so it is followed best-effort: to NNN itself, and if that is a table
+//! of jumps, to every jump of the table. Whatever isnt reached is data.
+
+/// What a walk over the rom found
+pub struct Analysis {
+    /// Every reachable instruction, by address
+    pub instructions: BTreeMap<usize, Instruction>,
+    /// Basic blocks, by their first address
+    pub blocks: BTreeMap<usize, Block>,
+    /// Entry of each subroutine, along with the blocks it reaches without calling anything,
+    /// [`ORIGIN`] is counted as one
+    pub subroutines: BTreeMap<usize, BTreeSet<usize>>,
+    /// The bytes that arent code
+    pub data: Vec<DataRegion>,
+    /// The instructions that write to the memory
+    pub writes: Vec<MemoryWrite>,
+}
+
+/// A run of instructions that is only entered at the top and only left at the bottom,
+/// not counting calls
+pub struct Block {
+    /// Address of the first instruction
+    pub start: usize,
+    /// Address right after the last instruction
+    pub end: usize,
+    pub successors: Vec<Edge>,
+    /// Subroutines called from the block, in order
+    pub calls: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// Falling through to the next instruction
+    Next,
+    /// `1NNN`
+    Jump,
+    /// Skipping the next instruction
+    Skip,
+    /// `BNNN`, a guess
+    Table,
+}
+
+pub struct DataRegion {
+    pub range: Range<usize>,
+    pub kind: DataKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataKind {
+    /// Drawn by a `DXYN`
+    Sprite,
+    /// Anything else
+    Data,
+}
+
+/// `FX55`, `FX33` or `5XY2`
+pub struct MemoryWrite {
+    /// Address of the instruction
+    pub address: usize,
+    pub instruction: Instruction,
+    /// The bytes written, if I is known from the `ANNN` before it
+    pub target: Option<Range<usize>>,
+    /// Whether those bytes are also executed, i.e. the code modifies itself
+    pub writes_code: bool,
+}
+
+/// Most entries a table of jumps can have, V0 only goes upto 255
+const MAX_TABLE_ENTRIES: usize = 128;
+
+impl Analysis {
+    pub fn new(rom: &[u8]) -> Self {
+        let mut instructions = BTreeMap::new();
+        let mut edges = BTreeMap::new();
+        let mut calls = BTreeSet::new();
+        let mut pending = vec![ORIGIN];
+        while let Some(address) = pending.pop() {
+            if instructions.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = instruction_at(rom, address) else {
+                continue;
+            };
+            instructions.insert(address, instruction);
+            if let Instruction::Call(target) = instruction {
+                calls.insert(target as usize);
+                pending.push(target as usize);
+            }
+            let successors = successors(rom, address, instruction);
+            pending.extend(successors.iter().map(|edge| edge.to));
+            edges.insert(address, successors);
+        }
+
+        let blocks = find_blocks(&instructions, &edges, &calls);
+        let subroutines = std::iter::once(ORIGIN)
+            .chain(calls.iter().copied())
+            .filter(|entry| blocks.contains_key(entry))
+            .map(|entry| (entry, reachable_blocks(&blocks, entry)))
+            .collect();
+        let mut analysis = Analysis {
+            instructions,
+            blocks,
+            subroutines,
+            data: Vec::new(),
+            writes: Vec::new(),
+        };
+        let (sprites, loaded) = analysis.follow_i(rom);
+        analysis.data = analysis.find_data(rom, &sprites, &loaded);
+        analysis
+    }
+
+    /// Whether `address` is in the bytes of a reachable instruction
+    pub fn is_code(&self, address: usize) -> bool {
+        self.instructions
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(&start, instruction)| address < start + instruction.size())
+    }
+
+    /// Keeps track of I through the blocks, to find what is drawn and what is written.
+    /// Returns the ranges drawn as sprites, and every address loaded into I.
+    fn follow_i(&mut self, rom: &[u8]) -> (Vec<Range<usize>>, BTreeSet<usize>) {
+        let i_at_blocks = self.i_at_blocks(rom);
+        let mut sprites = Vec::new();
+        let mut loaded = BTreeSet::new();
+        let mut writes = Vec::new();
+        for block in self.blocks.values() {
+            let mut i = i_at_blocks.get(&block.start).copied().flatten();
+            for (&address, &instruction) in self.instructions.range(block.start..block.end) {
+                let at_i = |len: usize| i.map(|i: usize| i..i + len);
+                match instruction {
+                    Instruction::Draw { height, .. } => {
+                        let len = if height == 0 { 32 } else { height as usize };
+                        sprites.extend(at_i(len));
+                    }
+                    Instruction::Store { x } => writes.push((address, instruction, at_i(x + 1))),
+                    Instruction::Bcd { .. } => writes.push((address, instruction, at_i(3))),
+                    Instruction::SaveRange { x, y } => {
+                        writes.push((address, instruction, at_i(x.abs_diff(y) + 1)));
+                    }
+                    _ => {}
+                }
+                i = i_after(rom, address, instruction, i);
+                if matches!(instruction, Instruction::LoadI(_) | Instruction::LoadILong) {
+                    loaded.extend(i);
+                }
+            }
+        }
+        self.writes = writes
+            .into_iter()
+            .map(|(address, instruction, target)| MemoryWrite {
+                address,
+                instruction,
+                writes_code: target
+                    .clone()
+                    .is_some_and(|mut range| range.any(|a| self.is_code(a))),
+                target,
+            })
+            .collect();
+        (sprites, loaded)
+    }
+
+    /// What I holds at the start of every block, `None` where it isnt known.
+    /// The rom and the subroutines start with I unknown, and I is carried from a block
+    /// to the blocks after it, as long as all the ways into a block agree on it.
+    /// A call forgets I, whatever the callee does to it
+    fn i_at_blocks(&self, rom: &[u8]) -> BTreeMap<usize, Option<usize>> {
+        let mut i_at_blocks: BTreeMap<usize, Option<usize>> =
+            self.subroutines.keys().map(|&entry| (entry, None)).collect();
+        let mut pending: Vec<usize> = i_at_blocks.keys().copied().collect();
+        while let Some(start) = pending.pop() {
+            let Some(block) = self.blocks.get(&start) else {
+                continue;
+            };
+            let mut i = i_at_blocks[&start];
+            for (&address, &instruction) in self.instructions.range(block.start..block.end) {
+                i = i_after(rom, address, instruction, i);
+            }
+            for edge in &block.successors {
+                let merged = match i_at_blocks.get(&edge.to) {
+                    None => i,
+                    Some(&known) if known == i => known,
+                    Some(_) => None,
+                };
+                if i_at_blocks.insert(edge.to, merged) != Some(merged) {
+                    pending.push(edge.to);
+                }
+            }
+        }
+        i_at_blocks
+    }
+
+    /// Runs of bytes that arent code, as sprites if any of them gets drawn.
+    /// A run is split wherever I points to, as that is usually where the next sprite or table starts.
+    fn find_data(
+        &self,
+        rom: &[u8],
+        sprites: &[Range<usize>],
+        loaded: &BTreeSet<usize>,
+    ) -> Vec<DataRegion> {
+        let mut data = Vec::new();
+        let end = ORIGIN + rom.len();
+        let mut address = ORIGIN;
+        while address < end {
+            if let Some(instruction) = self.instructions.get(&address) {
+                address += instruction.size();
+                continue;
+            }
+            let start = address;
+            address += 1;
+            while address < end
+                && !self.instructions.contains_key(&address)
+                && !loaded.contains(&address)
+            {
+                address += 1;
+            }
+            let drawn = sprites
+                .iter()
+                .any(|sprite| sprite.start < address && start < sprite.end);
+            data.push(DataRegion {
+                range: start..address,
+                kind: if drawn {
+                    DataKind::Sprite
+                } else {
+                    DataKind::Data
+                },
+            });
+        }
+        data
+    }
+
+    /// Name of a subroutine or block, `main` for [`ORIGIN`]
+    pub fn name(&self, address: usize) -> String {
+        match address {
+            ORIGIN => "main".to_string(),
+            _ if self.subroutines.contains_key(&address) => format!("sub_{address:03X}"),
+            _ => format!("block_{address:03X}"),
+        }
+    }
+
+    /// The control flow graph in Graphviz's DOT, a box per basic block with its assembly.
+    /// Calls are dashed, skips are labelled.
+    pub fn dot(&self, rom: &[u8]) -> String {
+        let mut out =
+            String::from("digraph rom {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = format!("{}\\l", self.name(block.start));
+            for (&address, instruction) in self.instructions.range(block.start..block.end) {
+                let long = opcode_at(rom, address + 2);
+                let text = instruction.format(Syntax::Octo, long, &|_| None);
+                label.push_str(&format!(
+                    "0x{address:04X}  {}\\l",
+                    text.replace('"', "\\\"")
+                ));
+            }
+            out.push_str(&format!(
+                "    \"0x{:04X}\" [label=\"{label}\"];\n",
+                block.start
+            ));
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => "",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                    EdgeKind::Table => " [label=\"table\", style=dotted]",
+                };
+                out.push_str(&format!(
+                    "    \"0x{:04X}\" -> \"0x{:04X}\"{style};\n",
+                    block.start, edge.to
+                ));
+            }
+            for call in &block.calls {
+                out.push_str(&format!(
+                    "    \"0x{:04X}\" -> \"0x{call:04X}\" [label=\"call\", style=dashed];\n",
+                    block.start
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Human readable summary, of the subroutines, data and the code that modifies itself
+    pub fn report(&self) -> String {
+        let mut out = format!(
+            "{} instructions in {} basic blocks, {} subroutines\n",
+            self.instructions.len(),
+            self.blocks.len(),
+            self.subroutines.len().saturating_sub(1),
+        );
+
+        out.push_str("\nSubroutines:\n");
+        for (&entry, blocks) in &self.subroutines {
+            let blocks: Vec<_> = blocks
+                .iter()
+                .map(|block| format!("0x{block:04X}"))
+                .collect();
+            out.push_str(&format!(
+                "    {} (0x{entry:04X}): {}\n",
+                self.name(entry),
+                blocks.join(" ")
+            ));
+        }
+
+        out.push_str("\nData:\n");
+        for region in &self.data {
+            let kind = match region.kind {
+                DataKind::Sprite => "sprite",
+                DataKind::Data => "data",
+            };
+            let range = &region.range;
+            out.push_str(&format!(
+                "    0x{:04X}..0x{:04X}  {kind}, {} bytes\n",
+                range.start,
+                range.end,
+                range.len()
+            ));
+        }
+
+        let describe = |write: &MemoryWrite| {
+            let text = write.instruction.format(Syntax::Octo, None, &|_| None);
+            match &write.target {
+                Some(target) => format!(
+                    "    0x{:04X}  {text}, writes 0x{:04X}..0x{:04X}\n",
+                    write.address, target.start, target.end
+                ),
+                None => format!("    0x{:04X}  {text}\n", write.address),
+            }
+        };
+        out.push_str("\nSelf-modifying code:\n");
+        for write in self.writes.iter().filter(|write| write.writes_code) {
+            out.push_str(&describe(write));
+        }
+        out.push_str("\nWrites through an I that isnt known, which may also modify code:\n");
+        for write in self.writes.iter().filter(|write| write.target.is_none()) {
+            out.push_str(&describe(write));
+        }
+        out
+    }
+}
+
+/// Where the instruction at `address` may go next, calls are left out
+fn successors(rom: &[u8], address: usize, instruction: Instruction) -> Vec<Edge> {
+    let next = address + instruction.size();
+    let edge = |to, kind| Edge { to, kind };
+    match instruction {
+        Instruction::Jump(target) => vec![edge(target as usize, EdgeKind::Jump)],
+        Instruction::Return | Instruction::Exit => Vec::new(),
+        Instruction::JumpOffset(target) => {
+            let target = target as usize;
+            let table: Vec<_> = (0..MAX_TABLE_ENTRIES)
+                .map(|n| target + n * 2)
+                .take_while(|&entry| {
+                    matches!(instruction_at(rom, entry), Some(Instruction::Jump(_)))
+                })
+                .map(|entry| edge(entry, EdgeKind::Table))
+                .collect();
+            match table.is_empty() {
+                true => vec![edge(target, EdgeKind::Table)],
+                false => table,
+            }
+        }
+        skip if skip.is_skip() => {
+            // the skipped instruction may be a 4 byte `F000 NNNN`
+            let skipped = instruction_at(rom, next).map_or(2, |instruction| instruction.size());
+            vec![
+                edge(next, EdgeKind::Next),
+                edge(next + skipped, EdgeKind::Skip),
+            ]
+        }
+        _ => vec![edge(next, EdgeKind::Next)],
+    }
+}
+
+/// What I holds after the instruction, given what it held before
+fn i_after(rom: &[u8], address: usize, instruction: Instruction, i: Option<usize>) -> Option<usize> {
+    match instruction {
+        Instruction::LoadI(target) => Some(target as usize),
+        Instruction::LoadILong => opcode_at(rom, address + 2).map(usize::from),
+        // `FX55` and `FX65` depend on the load/store quirk, and the callee can change I too
+        Instruction::Call(_)
+        | Instruction::Store { .. }
+        | Instruction::Restore { .. }
+        | Instruction::AddI { .. }
+        | Instruction::Font { .. }
+        | Instruction::BigFont { .. } => None,
+        _ => i,
+    }
+}
+
+fn find_blocks(
+    instructions: &BTreeMap<usize, Instruction>,
+    edges: &BTreeMap<usize, Vec<Edge>>,
+    calls: &BTreeSet<usize>,
+) -> BTreeMap<usize, Block> {
+    // blocks start at the entries, and wherever control can land other than by falling through
+    let mut leaders: BTreeSet<usize> = calls.iter().copied().chain([ORIGIN]).collect();
+    for successors in edges.values() {
+        let falls_through = matches!(
+            successors.as_slice(),
+            [Edge {
+                kind: EdgeKind::Next,
+                ..
+            }]
+        );
+        if !falls_through {
+            leaders.extend(successors.iter().map(|edge| edge.to));
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut current: Option<Block> = None;
+    for (&address, instruction) in instructions {
+        let falls_into = current.as_ref().is_some_and(|block| {
+            block.end == address
+                && block.successors
+                    == [Edge {
+                        to: address,
+                        kind: EdgeKind::Next,
+                    }]
+        });
+        if leaders.contains(&address) || !falls_into {
+            if let Some(block) = current.take() {
+                blocks.insert(block.start, block);
+            }
+        }
+        let block = current.get_or_insert(Block {
+            start: address,
+            end: address,
+            successors: Vec::new(),
+            calls: Vec::new(),
+        });
+        block.end = address + instruction.size();
+        block.successors = edges[&address].clone();
+        if let Instruction::Call(target) = instruction {
+            block.calls.push(*target as usize);
+        }
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+    blocks
+}
+
+/// The blocks reachable from `entry`, without following calls
+fn reachable_blocks(blocks: &BTreeMap<usize, Block>, entry: usize) -> BTreeSet<usize> {
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(start) = pending.pop() {
+        if !reached.insert(start) {
+            continue;
+        }
+        if let Some(block) = blocks.get(&start) {
+            pending.extend(block.successors.iter().map(|edge| edge.to));
+        }
+    }
+    reached.retain(|start| blocks.contains_key(start));
+    reached
+}
+
+use crate::cpu::{Instruction, Syntax};
+use crate::disasm::{instruction_at, opcode_at, ORIGIN};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn analyze(source: &str) -> Analysis {
+        Analysis::new(&assemble(source).unwrap())
+    }
+
+    #[test]
+    fn i_is_carried_into_a_loop() {
+        let analysis = analyze(
+            ": main i := sprite loop sprite v0 v1 3 v0 += 1 again
+             : sprite 0x3C 0x42 0x3C",
+        );
+        let kinds: Vec<_> = analysis.data.iter().map(|d| (d.range.clone(), d.kind)).collect();
+        assert_eq!(kinds, [(0x208..0x20B, DataKind::Sprite)]);
+    }
+
+    #[test]
+    fn i_is_unknown_where_the_ways_in_disagree() {
+        let analysis = analyze(
+            ": main i := a if v0 == 1 then i := b sprite v0 v1 1 loop again
+             : a 0xFF : b 0x81",
+        );
+        assert!(analysis.data.iter().all(|d| d.kind == DataKind::Data));
+    }
+
+    #[test]
+    fn a_call_forgets_i() {
+        let analysis = analyze(
+            ": main i := main setup save v1 loop again
+             : setup i := buffer return
+             : buffer 0 0",
+        );
+        let save = analysis.writes.iter().find(|w| w.address == 0x204).unwrap();
+        assert_eq!(save.target, None);
+        assert!(!save.writes_code);
+    }
+}
@@ -0,0 +1,667 @@
+//! Turns Octo source into a rom.
+//!
+//! The supported subset of Octo:
+//! - labels `: name`, `:const NAME VALUE`, `:alias NAME vX`, `:org ADDR`
+//! - `jump`, `jump0`, `:call`, calling a label by just its name, `return` or `;`
+//! - `vX := ...`, `vX += ...` and the other assignments, `i := ...`, `i += vX`,
+//!   `delay := vX`, `buzzer := vX`, `pitch := vX`
+//! - `if ... then`, `if ... begin ... else ... end`, `loop ... while ... again`,
+//!   with the conditions `==`, `!=`, `key` and `-key`
+//! - `sprite`, `clear`, `bcd`, `save`, `load`, and the SUPER-CHIP/XO-CHIP instructions
+//! - plain numbers, which are bytes of data
+//!
+//! Like in Octo the program starts at `: main`. Unless `main` is the first thing in the source,
+//! the rom starts with a `jump main` to it.
+
+/// Why a source didnt assemble
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    /// 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// The rom of the source, to be loaded at [`ORIGIN`]
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_symbols(source).map(|(rom, _)| rom)
+}
+
+/// Same as [`assemble`], along with the addresses of all the labels
+pub fn assemble_with_symbols(source: &str) -> Result<(Vec<u8>, Symbols), AssembleError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text,
+                line: index + 1,
+            })
+        })
+        .collect();
+    let mut assembler = Assembler {
+        tokens,
+        next: 0,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    if !assembler.starts_with_main() {
+        assembler.fixups.push(Fixup {
+            at: 0,
+            label: "main".to_string(),
+            line: 1,
+            long: false,
+        });
+        assembler.emit(0x1000);
+    }
+    while let Some(token) = assembler.take() {
+        assembler.statement(token)?;
+    }
+    assembler.finish()
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// A label used before the address is known
+struct Fixup {
+    /// Offset in the rom of the 2 bytes to patch
+    at: usize,
+    label: String,
+    line: usize,
+    /// `i := long`, all 16 bits are the address
+    long: bool,
+}
+
+/// The open `if ... begin` and `loop` blocks
+enum Block {
+    /// Offset of the jump to the `else` or the `end`
+    If { jump: usize },
+    /// Offset of the jump to the `end`
+    Else { jump: usize },
+    /// Where `again` goes back to, and offsets of the jumps out of the loop of each `while`
+    Loop { start: usize, exits: Vec<usize> },
+}
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    next: usize,
+    rom: Vec<u8>,
+    labels: HashMap<&'a str, usize>,
+    constants: HashMap<&'a str, i64>,
+    aliases: HashMap<&'a str, usize>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+}
+
+/// An operand that is either a register or a number,
+/// the numbers are read again with the range of the instruction
+enum Operand {
+    Register(usize),
+    Number,
+}
+
+impl<'a> Assembler<'a> {
+    /// Whether `: main` comes before any code, past the `:const` and `:alias` that dont emit any
+    fn starts_with_main(&self) -> bool {
+        let mut n = 0;
+        while matches!(self.tokens.get(n).map(|t| t.text), Some(":const" | ":alias")) {
+            n += 3;
+        }
+        let texts: Vec<_> = self.tokens.iter().skip(n).take(2).map(|t| t.text).collect();
+        texts == [":", "main"]
+    }
+
+    fn take(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.next).copied();
+        self.next += 1;
+        token
+    }
+
+    /// The line of the last token taken, for the errors
+    fn line(&self) -> usize {
+        let index = self.next.min(self.tokens.len()).saturating_sub(1);
+        self.tokens.get(index).map_or(1, |token| token.line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn expect_any(&mut self) -> Result<&'a str, AssembleError> {
+        match self.take() {
+            Some(token) => Ok(token.text),
+            None => self.error("The source ends too soon"),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssembleError> {
+        match self.expect_any()? {
+            found if found == text => Ok(()),
+            found => self.error(format!("Expected `{text}`, found `{found}`")),
+        }
+    }
+
+    fn here(&self) -> usize {
+        ORIGIN + self.rom.len()
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.rom.extend(opcode.to_be_bytes());
+    }
+
+    /// Puts `address` in the lower 12 bits of the opcode at `at`
+    fn patch(&mut self, at: usize, address: usize) {
+        self.rom[at] = (self.rom[at] & 0xF0) | (address >> 8) as u8 & 0x0F;
+        self.rom[at + 1] = address as u8;
+    }
+
+    /// Points the jump at `at` to here, right after the `word` that ends its block
+    fn patch_here(&mut self, at: usize, word: &str) -> Result<(), AssembleError> {
+        let here = self.here();
+        if here > 0xFFF {
+            return self.error(format!("The `{word}` is at 0x{here:X}, out of reach of 12 bits"));
+        }
+        self.patch(at, here);
+        Ok(())
+    }
+
+    #[rustfmt::skip]
+    fn statement(&mut self, token: Token<'a>) -> Result<(), AssembleError> {
+        match token.text {
+            ":" => {
+                let name = self.expect_any()?;
+                if self.labels.insert(name, self.here()).is_some() {
+                    return self.error(format!("The label `{name}` is defined twice"));
+                }
+            }
+            ":const" => {
+                let name = self.expect_any()?;
+                let value = self.expect_any()?;
+                let value = self.number(value)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.expect_any()?;
+                let register = self.expect_any()?;
+                let register = self.register(register)?;
+                self.aliases.insert(name, register);
+            }
+            ":org" => {
+                let address = self.expect_any()?;
+                let address = self.number(address)? as usize;
+                if address < self.here() {
+                    return self.error(format!("`:org 0x{address:X}` would go back over the code already there"));
+                }
+                if address >= Memory::XO_CHIP_SIZE {
+                    return self.error(format!("`:org 0x{address:X}` is past the end of the memory"));
+                }
+                self.rom.resize(address - ORIGIN, 0);
+            }
+            ":call" => self.address(0x2000)?,
+            "jump" => self.address(0x1000)?,
+            "jump0" => self.address(0xB000)?,
+            "return" | ";" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "scroll-down" => { let n = self.nibble()?; self.emit(0x00C0 | n) }
+            "scroll-up" => { let n = self.nibble()?; self.emit(0x00D0 | n) }
+            "audio" => self.emit(0xF002),
+            "plane" => { let n = self.nibble()?; self.emit(0xF001 | n << 8) }
+            "bcd" => { let x = self.next_register()?; self.emit(0xF033 | x << 8) }
+            "saveflags" => { let x = self.next_register()?; self.emit(0xF075 | x << 8) }
+            "loadflags" => { let x = self.next_register()?; self.emit(0xF085 | x << 8) }
+            "save" => self.save_load(0xF055, 0x5002)?,
+            "load" => self.save_load(0xF065, 0x5003)?,
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "if" => {
+                let skip = self.condition()?;
+                match self.expect_any()? {
+                    "then" => self.emit(skip),
+                    "begin" => {
+                        self.emit(inverse(skip));
+                        self.blocks.push(Block::If { jump: self.rom.len() });
+                        self.emit(0x1000);
+                    }
+                    found => return self.error(format!("Expected `then` or `begin`, found `{found}`")),
+                }
+            }
+            "else" => {
+                let Some(Block::If { jump }) = self.blocks.pop() else {
+                    return self.error("`else` without an `if ... begin`");
+                };
+                self.blocks.push(Block::Else { jump: self.rom.len() });
+                self.emit(0x1000);
+                self.patch_here(jump, "else")?;
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump } | Block::Else { jump }) => self.patch_here(jump, "end")?,
+                _ => return self.error("`end` without an `if ... begin`"),
+            },
+            "loop" => self.blocks.push(Block::Loop { start: self.here(), exits: Vec::new() }),
+            "while" => {
+                let skip = self.condition()?;
+                let exit = self.rom.len() + 2;
+                let Some(Block::Loop { exits, .. }) = self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop { .. })) else {
+                    return self.error("`while` outside of a `loop`");
+                };
+                exits.push(exit);
+                self.emit(inverse(skip));
+                self.emit(0x1000);
+            }
+            "again" => {
+                let Some(Block::Loop { start, exits }) = self.blocks.pop() else {
+                    return self.error("`again` without a `loop`");
+                };
+                if start > 0xFFF {
+                    return self.error(format!("The `loop` is at 0x{start:X}, out of reach of 12 bits"));
+                }
+                self.emit(0x1000 | start as u16);
+                for exit in exits {
+                    self.patch_here(exit, "again")?;
+                }
+            }
+            "i" => match self.expect_any()? {
+                ":=" => match self.expect_any()? {
+                    "long" => {
+                        self.emit(0xF000);
+                        let target = self.expect_any()?;
+                        match self.value(target) {
+                            Some(address) => self.emit(address as u16),
+                            None => {
+                                self.fixups.push(Fixup { at: self.rom.len(), label: target.to_string(), line: self.line(), long: true });
+                                self.emit(0);
+                            }
+                        }
+                    }
+                    "hex" => { let x = self.next_register()?; self.emit(0xF029 | x << 8) }
+                    "bighex" => { let x = self.next_register()?; self.emit(0xF030 | x << 8) }
+                    _ => { self.next -= 1; self.address(0xA000)? }
+                },
+                "+=" => { let x = self.next_register()?; self.emit(0xF01E | x << 8) }
+                found => return self.error(format!("Expected `:=` or `+=` after `i`, found `{found}`")),
+            },
+            "delay" => { self.expect(":=")?; let x = self.next_register()?; self.emit(0xF015 | x << 8) }
+            "buzzer" => { self.expect(":=")?; let x = self.next_register()?; self.emit(0xF018 | x << 8) }
+            "pitch" => { self.expect(":=")?; let x = self.next_register()?; self.emit(0xF03A | x << 8) }
+            text => {
+                if let Ok(x) = self.register(text) {
+                    return self.assignment(x as u16);
+                }
+                match self.labels.contains_key(text) || self.value(text).is_none() {
+                    // a label on its own is a call
+                    true => { self.next -= 1; self.address(0x2000)? }
+                    false => {
+                        let byte = self.byte(text)?;
+                        self.rom.push(byte);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `vX op ...`
+    #[rustfmt::skip]
+    fn assignment(&mut self, x: u16) -> Result<(), AssembleError> {
+        let op = self.expect_any()?;
+        let operand = self.expect_any()?;
+        let x = x << 8;
+        let opcode = match (op, operand) {
+            (":=", "random") => 0xC000 | x | self.next_byte()? as u16,
+            (":=", "delay") => 0xF007 | x,
+            (":=", "key") => 0xF00A | x,
+            _ => match (op, self.operand(operand)?) {
+                (":=", Operand::Register(y)) => 0x8000 | x | (y as u16) << 4,
+                (":=", Operand::Number) => 0x6000 | x | self.byte(operand)? as u16,
+                ("+=", Operand::Register(y)) => 0x8004 | x | (y as u16) << 4,
+                ("+=", Operand::Number) => 0x7000 | x | self.byte(operand)? as u16,
+                ("-=", Operand::Register(y)) => 0x8005 | x | (y as u16) << 4,
+                ("-=", Operand::Number) => 0x7000 | x | (self.byte(operand)? as u16).wrapping_neg() & 0xFF,
+                ("=-", Operand::Register(y)) => 0x8007 | x | (y as u16) << 4,
+                ("|=", Operand::Register(y)) => 0x8001 | x | (y as u16) << 4,
+                ("&=", Operand::Register(y)) => 0x8002 | x | (y as u16) << 4,
+                ("^=", Operand::Register(y)) => 0x8003 | x | (y as u16) << 4,
+                (">>=", Operand::Register(y)) => 0x8006 | x | (y as u16) << 4,
+                ("<<=", Operand::Register(y)) => 0x800E | x | (y as u16) << 4,
+                _ => return self.error(format!("`{op} {operand}` is not a supported assignment")),
+            },
+        };
+        self.emit(opcode);
+        Ok(())
+    }
+
+    /// The opcode that skips the next instruction when the condition *doesnt* hold,
+    /// which is what `if ... then` compiles to
+    fn condition(&mut self) -> Result<u16, AssembleError> {
+        let x = self.next_register()? << 8;
+        let op = self.expect_any()?;
+        if op == "key" {
+            return Ok(0xE0A1 | x);
+        }
+        if op == "-key" {
+            return Ok(0xE09E | x);
+        }
+        let operand = self.expect_any()?;
+        Ok(match (op, self.operand(operand)?) {
+            ("==", Operand::Number) => 0x4000 | x | self.byte(operand)? as u16,
+            ("!=", Operand::Number) => 0x3000 | x | self.byte(operand)? as u16,
+            ("==", Operand::Register(y)) => 0x9000 | x | (y as u16) << 4,
+            ("!=", Operand::Register(y)) => 0x5000 | x | (y as u16) << 4,
+            _ => return self.error(format!("`{op}` is not a supported condition")),
+        })
+    }
+
+    /// `save vX` or `save vX - vY`
+    fn save_load(&mut self, single: u16, range: u16) -> Result<(), AssembleError> {
+        let x = self.next_register()?;
+        let dash = self.tokens.get(self.next).map(|token| token.text);
+        if dash == Some("-") {
+            self.next += 1;
+            let y = self.next_register()?;
+            self.emit(range | x << 8 | y << 4);
+        } else {
+            self.emit(single | x << 8);
+        }
+        Ok(())
+    }
+
+    /// An opcode with a 12 bit address, which may be a label not defined yet
+    fn address(&mut self, opcode: u16) -> Result<(), AssembleError> {
+        let target = self.expect_any()?;
+        let address = match self.value(target) {
+            Some(address @ 0..=0xFFF) => address as u16,
+            Some(address) => return self.error(format!("0x{address:X} doesnt fit in 12 bits")),
+            None => {
+                if !is_identifier(target) {
+                    return self.error(format!("`{target}` is not a label"));
+                }
+                self.fixups.push(Fixup {
+                    at: self.rom.len(),
+                    label: target.to_string(),
+                    line: self.line(),
+                    long: false,
+                });
+                0
+            }
+        };
+        self.emit(opcode | address);
+        Ok(())
+    }
+
+    fn next_register(&mut self) -> Result<u16, AssembleError> {
+        let text = self.expect_any()?;
+        self.register(text).map(|x| x as u16)
+    }
+
+    fn register(&self, text: &str) -> Result<usize, AssembleError> {
+        if let Some(&x) = self.aliases.get(text) {
+            return Ok(x);
+        }
+        text.strip_prefix(['v', 'V'])
+            .filter(|x| x.len() == 1)
+            .and_then(|x| usize::from_str_radix(x, 16).ok())
+            .map_or_else(|| self.error(format!("`{text}` is not a register")), Ok)
+    }
+
+    fn operand(&self, text: &str) -> Result<Operand, AssembleError> {
+        if let Ok(x) = self.register(text) {
+            return Ok(Operand::Register(x));
+        }
+        self.number(text).map(|_| Operand::Number)
+    }
+
+    fn next_byte(&mut self) -> Result<u8, AssembleError> {
+        let text = self.expect_any()?;
+        self.byte(text)
+    }
+
+    /// -128 to 255, the negative ones wrap around
+    fn byte(&self, text: &str) -> Result<u8, AssembleError> {
+        match self.number(text)? {
+            value @ -128..=255 => Ok(value as u8),
+            value => self.error(format!("{value} doesnt fit in a byte")),
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u16, AssembleError> {
+        let text = self.expect_any()?;
+        match self.number(text)? {
+            value @ 0..=15 => Ok(value as u16),
+            value => self.error(format!("{value} doesnt fit in 4 bits")),
+        }
+    }
+
+    fn number(&self, text: &str) -> Result<i64, AssembleError> {
+        self.value(text).map_or_else(
+            || self.error(format!("`{text}` is not a number or a constant")),
+            Ok,
+        )
+    }
+
+    /// A number, a constant, or a label that is already defined
+    fn value(&self, text: &str) -> Option<i64> {
+        if let Some(&value) = self.constants.get(text) {
+            return Some(value);
+        }
+        if let Some(&address) = self.labels.get(text) {
+            return Some(address as i64);
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn finish(mut self) -> Result<(Vec<u8>, Symbols), AssembleError> {
+        if let Some(block) = self.blocks.last() {
+            let message = match block {
+                Block::Loop { .. } => "A `loop` is missing its `again`",
+                _ => "An `if ... begin` is missing its `end`",
+            };
+            return self.error(message);
+        }
+        if !self.labels.contains_key("main") {
+            return Err(AssembleError {
+                line: 1,
+                message: "There is no `: main` for the program to start at".to_string(),
+            });
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(fixup.label.as_str()) else {
+                return Err(AssembleError {
+                    line: fixup.line,
+                    message: format!("Undefined label `{}`", fixup.label),
+                });
+            };
+            if fixup.long {
+                self.rom[fixup.at..fixup.at + 2].copy_from_slice(&(address as u16).to_be_bytes());
+            } else if address > 0xFFF {
+                return Err(AssembleError {
+                    line: fixup.line,
+                    message: format!(
+                        "`{}` is at 0x{address:X}, out of reach of 12 bits",
+                        fixup.label
+                    ),
+                });
+            } else {
+                self.patch(fixup.at, address);
+            }
+        }
+        let mut labels: Vec<_> = self.labels.iter().map(|(&name, &address)| (address, name)).collect();
+        labels.sort();
+        let mut symbols = Symbols::new();
+        for (address, name) in labels {
+            symbols.insert(address, name);
+        }
+        Ok((self.rom, symbols))
+    }
+}
+
+/// The skip that does the opposite
+fn inverse(skip: u16) -> u16 {
+    match skip >> 12 {
+        0x3 | 0x4 => skip ^ 0x7000,
+        0x5 | 0x9 => skip ^ 0xC000,
+        // EX9E and EXA1
+        _ => skip ^ 0x003F,
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+use crate::disasm::ORIGIN;
+use crate::memory::Memory;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(source: &str) -> Vec<u16> {
+        assemble(source)
+            .unwrap()
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().message
+    }
+
+    #[test]
+    fn if_then_skips_when_the_condition_fails() {
+        assert_eq!(words(": main if v1 == 5 then v2 := 1"), [0x4105, 0x6201]);
+        assert_eq!(words(": main if v1 != v2 then v2 := 1"), [0x5120, 0x6201]);
+        assert_eq!(words(": main if v3 key then v2 := 1"), [0xE3A1, 0x6201]);
+    }
+
+    #[test]
+    fn if_begin_else_end() {
+        assert_eq!(
+            words(": main if v0 != 0 begin v1 := 1 else v1 := 2 end"),
+            [0x4000, 0x1208, 0x6101, 0x120A, 0x6102]
+        );
+        assert_eq!(words(": main if v0 != 0 begin v1 := 1 end"), [0x4000, 0x1206, 0x6101]);
+    }
+
+    #[test]
+    fn loop_while_again() {
+        assert_eq!(
+            words(": main loop v0 += 1 while v0 != 10 again"),
+            [0x7001, 0x400A, 0x1208, 0x1200]
+        );
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        assert_eq!(words(": main jump done v0 := 1 : done return"), [0x1204, 0x6001, 0x00EE]);
+        assert_eq!(words(": main helper : helper return"), [0x2202, 0x00EE]);
+        assert_eq!(words(": main i := long far :org 0x1000 : far"), {
+            let mut rom = vec![0xF000, 0x1000];
+            rom.resize((0x1000 - ORIGIN) / 2, 0);
+            rom
+        });
+    }
+
+    #[test]
+    fn main_is_jumped_to_unless_it_comes_first() {
+        assert_eq!(words(":const N 1 : main v0 := N"), [0x6001]);
+        assert_eq!(
+            words(": helper v0 := 1 return : main helper jump main"),
+            [0x1206, 0x6001, 0x00EE, 0x2202, 0x1206]
+        );
+        assert_eq!(error("v0 := 1"), "There is no `: main` for the program to start at");
+    }
+
+    #[test]
+    fn org_moves_the_code() {
+        assert_eq!(
+            words(": main jump data :org 0x208 : data 1 2"),
+            [0x1208, 0, 0, 0, 0x0102]
+        );
+        assert_eq!(
+            error(": main clear clear :org 0x202"),
+            "`:org 0x202` would go back over the code already there"
+        );
+        assert_eq!(
+            error(": main :org 0x10000"),
+            "`:org 0x10000` is past the end of the memory"
+        );
+    }
+
+    #[test]
+    fn jumps_out_of_reach_are_errors() {
+        assert_eq!(
+            error(": main :org 0x1000 loop again"),
+            "The `loop` is at 0x1000, out of reach of 12 bits"
+        );
+        assert_eq!(
+            error(": main :org 0xFFC loop while v0 != 0 again"),
+            "The `again` is at 0x1002, out of reach of 12 bits"
+        );
+        assert_eq!(
+            error(": main :org 0xFFA if v0 != 0 begin else end"),
+            "The `else` is at 0x1000, out of reach of 12 bits"
+        );
+        assert_eq!(
+            error(": main :org 0xFFE if v0 != 0 begin v1 := 1 end"),
+            "The `end` is at 0x1004, out of reach of 12 bits"
+        );
+        assert_eq!(
+            error(": main jump far :org 0x1000 : far"),
+            "`far` is at 0x1000, out of reach of 12 bits"
+        );
+        assert_eq!(error(": main jump 0x1000"), "0x1000 doesnt fit in 12 bits");
+    }
+
+    #[test]
+    fn errors_have_the_line() {
+        let error = assemble(": main\n  clear\n  sprite vz v0 1\n").unwrap_err();
+        assert_eq!((error.line, error.message.as_str()), (3, "`vz` is not a register"));
+    }
+}
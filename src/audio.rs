@@ -0,0 +1,74 @@
+/// XO-CHIP's audio, a 128 bit pattern that keeps on looping while the sound timer is on.
+///
+/// Roms that never load a pattern get the usual beep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    /// Loaded by `F002`, one bit per sample, most significant bit first
+    pattern: Option<[u8; 16]>,
+    /// Set by `FX3A`, decides how fast the pattern is played
+    pitch: u8,
+}
+
+impl Audio {
+    /// Plays the pattern at 4000 bits per second
+    pub const DEFAULT_PITCH: u8 = 64;
+    /// Number of bits in a pattern
+    pub const PATTERN_BITS: usize = 128;
+
+    pub fn new() -> Self {
+        Audio {
+            pattern: None,
+            pitch: Self::DEFAULT_PITCH,
+        }
+    }
+
+    /// The pattern loaded by the rom, if any
+    pub fn pattern(&self) -> Option<&[u8; 16]> {
+        self.pattern.as_ref()
+    }
+
+    pub fn set_pattern(&mut self, pattern: [u8; 16]) {
+        self.pattern = Some(pattern);
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    /// Number of pattern bits played in a second, `4000*2^((pitch-64)/48)`
+    pub fn playback_rate(&self) -> f32 {
+        4000. * 2f32.powf((self.pitch as f32 - 64.) / 48.)
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for Audio {
+    fn save(&self, out: &mut StateWriter) {
+        out.bool(self.pattern.is_some());
+        out.bytes(&self.pattern.unwrap_or_default());
+        out.u8(self.pitch);
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        let has_pattern = input.bool()?;
+        let pattern: [u8; 16] = input
+            .bytes()?
+            .try_into()
+            .map_err(|_| SaveStateError::Invalid("audio pattern"))?;
+        Ok(Audio {
+            pattern: has_pattern.then_some(pattern),
+            pitch: input.u8()?,
+        })
+    }
+}
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
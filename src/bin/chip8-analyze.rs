@@ -0,0 +1,36 @@
+//! Prints the subroutines, data and self-modifying code of a rom,
+//! and writes its control flow graph for Graphviz.
+
+fn main() {
+    let args = Args::parse();
+
+    let rom = std::fs::read(&args.rom_path).unwrap_or_else(|e| {
+        eprintln!("Unable to open {}: {}", args.rom_path.display(), e);
+        std::process::exit(1);
+    });
+    let analysis = Analysis::new(&rom);
+    print!("{}", analysis.report());
+    if let Some(path) = &args.dot {
+        if let Err(e) = std::fs::write(path, analysis.dot(&rom)) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(clap::Parser)]
+#[clap(name = "chip8-analyze")]
+struct Args {
+    /// Path to the rom file
+    rom_path: PathBuf,
+
+    /// Writes the control flow graph in Graphviz's DOT,
+    /// e.g. `dot -Tsvg <PATH> -o rom.svg` draws it
+    #[clap(long, value_name = "PATH")]
+    dot: Option<PathBuf>,
+}
+
+use std::path::PathBuf;
+
+use chip_8_emulator::analysis::Analysis;
+use clap::Parser;
@@ -0,0 +1,47 @@
+//! Assembles Octo source into a rom, see [`chip_8_emulator::assembler`] for what it supports.
+
+fn main() {
+    let args = Args::parse();
+
+    let source = std::fs::read_to_string(&args.source_path).unwrap_or_else(|e| {
+        eprintln!("Unable to open {}: {}", args.source_path.display(), e);
+        std::process::exit(1);
+    });
+    let (rom, symbols) = assembler::assemble_with_symbols(&source).unwrap_or_else(|e| {
+        eprintln!("{}: {}", args.source_path.display(), e);
+        std::process::exit(1);
+    });
+    let output = args
+        .output
+        .unwrap_or_else(|| args.source_path.with_extension("ch8"));
+    if let Err(e) = std::fs::write(&output, &rom) {
+        eprintln!("Unable to write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+    if let Some(path) = &args.symbols {
+        if let Err(e) = std::fs::write(path, symbols.to_string()) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(clap::Parser)]
+#[clap(name = "chip8-asm")]
+struct Args {
+    /// Path to the Octo source
+    source_path: PathBuf,
+
+    /// Where to write the rom, the source with a .ch8 extension by default
+    #[clap(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Also writes the addresses of the labels, for the debugger and `chip8-disasm --symbols`
+    #[clap(long, value_name = "PATH")]
+    symbols: Option<PathBuf>,
+}
+
+use std::path::PathBuf;
+
+use chip_8_emulator::assembler;
+use clap::Parser;
@@ -0,0 +1,78 @@
+//! Prints the assembly of a rom, with the address and bytes of every line.
+//!
+//! Only the instructions that can be reached from the start of the rom are taken as code,
+//! `--all-code` takes everything that decodes instead.
+
+fn main() {
+    let args = Args::parse();
+
+    let rom = std::fs::read(&args.rom_path).unwrap_or_else(|e| {
+        eprintln!("Unable to open {}: {}", args.rom_path.display(), e);
+        std::process::exit(1);
+    });
+    let symbols = match &args.symbols {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Symbols::parse(&text).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to read the symbols {}: {}", path.display(), e);
+                std::process::exit(1);
+            }),
+        None => Symbols::new(),
+    };
+    let listing = disasm::disassemble(&rom, args.syntax.into(), !args.all_code, &symbols);
+    match &args.output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, listing) {
+                eprintln!("Unable to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{listing}"),
+    }
+}
+
+#[derive(clap::Parser)]
+#[clap(name = "chip8-disasm")]
+struct Args {
+    /// Path to the rom file
+    rom_path: PathBuf,
+
+    /// Assembly syntax of the listing
+    #[clap(short, long, default_value = "octo")]
+    syntax: SyntaxArg,
+
+    /// Disassembles every 2 bytes that decode, even the ones that look like data
+    #[clap(long)]
+    all_code: bool,
+
+    /// Names the labels after a symbol file, see [`chip_8_emulator::symbols`]
+    #[clap(long, value_name = "PATH")]
+    symbols: Option<PathBuf>,
+
+    /// Writes the listing to a file, instead of the stdout
+    #[clap(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum SyntaxArg {
+    /// `v0 := 0x05`, as in Octo
+    Octo,
+    /// `LD V0, 0x05`, as in Cowgod's reference
+    Classic,
+}
+
+impl From<SyntaxArg> for Syntax {
+    fn from(syntax: SyntaxArg) -> Self {
+        match syntax {
+            SyntaxArg::Octo => Syntax::Octo,
+            SyntaxArg::Classic => Syntax::Classic,
+        }
+    }
+}
+
+use std::path::PathBuf;
+
+use chip_8_emulator::{cpu::Syntax, disasm, symbols::Symbols};
+use clap::Parser;
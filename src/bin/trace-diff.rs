@@ -0,0 +1,55 @@
+//! Finds where two traces, as written by `--trace`, first part ways.
+//!
+//! Only the fields both traces have are compared, so the trace of another emulator
+//! just needs the same `key=value` fields.
+
+fn main() {
+    let args = Args::parse();
+
+    let read = |path: &PathBuf| {
+        std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Unable to open {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    };
+    let (left, right) = (read(&args.left), read(&args.right));
+    let Some(divergence) = trace::diff(&left, &right) else {
+        println!("The traces are the same");
+        return;
+    };
+
+    let show = |path: &PathBuf, line: &Option<String>| match line {
+        Some(line) => println!("{}: {}", path.display(), line),
+        None => println!("{}: <ended>", path.display()),
+    };
+    match divergence.fields.as_slice() {
+        [] => println!("The traces part ways at line {}", divergence.line),
+        fields => println!(
+            "The traces part ways at line {}, on {}",
+            divergence.line,
+            fields.join(", ")
+        ),
+    }
+    if divergence.line > 1 {
+        let previous = left.lines().nth(divergence.line - 2).unwrap_or_default();
+        println!("after: {previous}");
+    }
+    show(&args.left, &divergence.left);
+    show(&args.right, &divergence.right);
+    std::process::exit(1);
+}
+
+#[derive(clap::Parser)]
+#[clap(name = "trace-diff")]
+struct Args {
+    /// The first trace
+    left: PathBuf,
+
+    /// The trace to compare it with
+    right: PathBuf,
+}
+
+use std::path::PathBuf;
+
+use chip_8_emulator::trace;
+use clap::Parser;
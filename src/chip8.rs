@@ -0,0 +1,304 @@
+/// The whole CHIP-8 machine.
+///
+/// Owns the CPU along with its memory, graphics memory, timers and keypad,
+/// nothing here is shared or locked, wrap it yourself if needed.
+pub struct Chip8 {
+    cpu: CPU,
+    /// Kept around for resetting the machine
+    rom: Vec<u8>,
+    /// 4KiB, or 64KiB for XO-CHIP
+    memory_size: usize,
+    /// The error that halted the CPU, cleared only by a reset
+    fault: Option<ExecutionError>,
+    /// Number of instructions executed in one [`Chip8::run_frame`]
+    instructions_per_frame: u32,
+    cycles: u64,
+}
+
+impl Chip8 {
+    /// The original emulator ran the CPU at 500Hz and the timers at 60Hz
+    pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 500 / 60;
+
+    pub fn new(rom: &[u8]) -> Result<Self, &'static str> {
+        Self::with_memory_size(rom, Memory::SIZE)
+    }
+
+    /// A machine with the 64KiB memory of XO-CHIP, and its quirks
+    pub fn new_xo_chip(rom: &[u8]) -> Result<Self, &'static str> {
+        let mut chip8 = Self::with_memory_size(rom, Memory::XO_CHIP_SIZE)?;
+        chip8.set_quirks(Quirks::XO_CHIP);
+        Ok(chip8)
+    }
+
+    fn with_memory_size(rom: &[u8], memory_size: usize) -> Result<Self, &'static str> {
+        Ok(Chip8 {
+            cpu: CPU::new(rom, Quirks::default(), memory_size)?,
+            rom: rom.to_vec(),
+            memory_size,
+            fault: None,
+            instructions_per_frame: Self::DEFAULT_INSTRUCTIONS_PER_FRAME,
+            cycles: 0,
+        })
+    }
+
+    /// Executes a single instruction
+    ///
+    /// Once an instruction fails, the machine is halted, and every further step
+    /// returns the same error without executing anything, till [`Chip8::reset`].
+    pub fn step(&mut self) -> Result<(), ExecutionError> {
+        if let Some(fault) = &self.fault {
+            return Err(fault.clone());
+        }
+        if self.cpu.exited() {
+            return Ok(());
+        }
+        if let Err(e) = self.cpu.cycle() {
+            tracing::error!("CPU halted: {}", e);
+            self.fault = Some(e.clone());
+            return Err(e);
+        }
+        self.cycles += 1;
+        Ok(())
+    }
+
+    /// Same as [`Chip8::step`], but also returns how to take the instruction back
+    /// with [`Chip8::undo`], unless nothing was executed
+    pub fn step_with_undo(&mut self) -> Result<Option<Undo>, ExecutionError> {
+        if let Some(fault) = &self.fault {
+            return Err(fault.clone());
+        }
+        if self.cpu.exited() {
+            return Ok(None);
+        }
+        let (result, undo) = self.cpu.cycle_with_undo();
+        if let Err(e) = result {
+            tracing::error!("CPU halted: {}", e);
+            self.fault = Some(e.clone());
+            return Err(e);
+        }
+        self.cycles += 1;
+        Ok(Some(undo))
+    }
+
+    /// Takes back the instruction [`Chip8::step_with_undo`] executed,
+    /// they have to be undone in the reverse order. Clears any fault.
+    pub fn undo(&mut self, undo: Undo) {
+        self.cpu.undo(undo);
+        self.cycles = self.cycles.saturating_sub(1);
+        self.fault = None;
+    }
+
+    /// Executes a frame worth of instructions and then ticks the timers once,
+    /// meant to be called at 60Hz
+    pub fn run_frame(&mut self) -> Result<(), ExecutionError> {
+        for _ in 0..self.instructions_per_frame {
+            self.step()?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Starts the loaded rom all over again, clearing any fault
+    ///
+    /// The SUPER-CHIP user flags survive the reset, like they did on the calculators.
+    /// The random numbers start over from the same seed.
+    pub fn reset(&mut self) {
+        tracing::info!("Resetting the machine");
+        let quirks = *self.cpu.quirks();
+        let rpl_flags = *self.cpu.rpl_flags();
+        let mut rng = self.cpu.rng().clone();
+        rng.restart();
+        self.cpu =
+            CPU::new(&self.rom, quirks, self.memory_size).expect("The rom was already loaded once");
+        self.cpu.set_rpl_flags(rpl_flags);
+        self.cpu.set_rng(rng);
+        self.fault = None;
+        self.cycles = 0;
+    }
+
+    /// Whether the rom exited, with SUPER-CHIP's `00FD`.
+    /// Stepping an exited machine does nothing.
+    pub fn exited(&self) -> bool {
+        self.cpu.exited()
+    }
+
+    /// The error that halted the CPU, if any
+    pub fn fault(&self) -> Option<&ExecutionError> {
+        self.fault.as_ref()
+    }
+
+    /// Decrements the delay and the sound timer
+    pub fn tick_timers(&mut self) {
+        self.cpu.tick_timers();
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Defaults to the quirks of the COSMAC VIP
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        self.cpu.quirks()
+    }
+
+    /// Defaults to a random seed, see [`Rng::from_entropy`]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.cpu.set_rng(Rng::seeded(seed));
+    }
+
+    /// e.g. a [`Rng::scripted`] one, to test a rom
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.cpu.set_rng(rng);
+    }
+
+    pub fn rng(&self) -> &Rng {
+        self.cpu.rng()
+    }
+
+    pub fn press_key(&mut self, key: Key) {
+        self.cpu.inputs_mut().update(key, true);
+    }
+
+    pub fn release_key(&mut self, key: Key) {
+        self.cpu.inputs_mut().update(key, false);
+    }
+
+    /// Number of instructions executed since the start
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn memory(&self) -> &Memory {
+        self.cpu.memory()
+    }
+
+    pub fn graphics_memory(&self) -> &GraphicsMemory {
+        self.cpu.graphics_memory()
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        self.cpu.registers()
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.cpu.i_register()
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.cpu.instruction_ptr()
+    }
+
+    /// Changing the machine by hand, from a debugger.
+    /// `index` is 0x0 to 0xF
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.cpu.set_register(index, value);
+    }
+
+    pub fn set_i_register(&mut self, value: u16) {
+        self.cpu.set_i_register(value);
+    }
+
+    pub fn set_program_counter(&mut self, address: usize) {
+        self.cpu.set_instruction_ptr(address);
+    }
+
+    /// Panics if the address is outside of the memory
+    pub fn set_memory(&mut self, address: usize, value: u8) {
+        self.cpu.memory_mut()[address] = value;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.cpu.set_delay_timer(value);
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.cpu.set_sound_timer(value);
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        self.cpu.stack()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.cpu.delay_timer()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.cpu.sound_timer()
+    }
+
+    /// The XO-CHIP audio pattern and pitch, played while the sound timer is on
+    pub fn audio(&self) -> &Audio {
+        self.cpu.audio()
+    }
+
+    /// The memory read/written by the last step, see [`crate::debugger`]'s watchpoints
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        self.cpu.memory_accesses()
+    }
+
+    pub fn inputs(&self) -> &InpuState {
+        self.cpu.inputs()
+    }
+
+    /// Snapshot of the complete machine, in the format of [`crate::savestate`]
+    ///
+    /// The rom goes along with it, resetting a loaded state starts its rom again.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        out.bytes(&self.rom);
+        out.u64(self.cycles);
+        self.cpu.save(&mut out);
+        out.finish()
+    }
+
+    /// Restores a snapshot taken by [`Chip8::save_state`]
+    ///
+    /// A broken save state leaves the machine as it was. A fault is not part of the state,
+    /// a machine saved after faulting faults again on the next step.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut input = StateReader::new(state)?;
+        let rom = input.bytes()?.to_vec();
+        let cycles = input.u64()?;
+        let cpu = CPU::load(&mut input)?;
+        input.finish()?;
+
+        self.memory_size = cpu.memory().len();
+        self.rom = rom;
+        self.cycles = cycles;
+        self.cpu = cpu;
+        self.fault = None;
+        Ok(())
+    }
+
+    /// CPU state along with the opcode that is going to be executed next
+    pub fn dump(&self) -> String {
+        self.cpu.dump(self.cpu.peek_opcode())
+    }
+
+    /// Same as [`Chip8::dump`], but skips the memory
+    pub fn dump_without_memory(&self) -> String {
+        self.cpu.dump_without_memory(self.cpu.peek_opcode())
+    }
+}
+
+use crate::audio::Audio;
+use crate::cpu::{ExecutionError, Undo, CPU};
+use crate::graphics::GraphicsMemory;
+use crate::input::{InpuState, Key};
+use crate::memory::{Memory, MemoryAccess};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
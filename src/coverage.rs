@@ -0,0 +1,175 @@
+//! Which bytes of the memory a run executed, read and wrote, to find the code a test never reached.
+//!
+//! [`Coverage::json`] has the ranges of each, along with the instructions that can be reached
+//! from the start of the rom but never ran. [`Coverage::listing`] is the disassembly of the rom
+//! with how many times each instruction ran, `#####` for never, like gcov does.
+
+/// How many times every byte of the memory was executed, read and written
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// Both bytes of an instruction count as executed, the 4 of `F000 NNNN`
+    executed: Vec<u32>,
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+    /// Where the executed instructions start
+    starts: BTreeSet<usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the instruction that the machine just executed from `pc`, and what it read/wrote
+    pub fn record(&mut self, pc: usize, machine: &Chip8) {
+        let memory = machine.memory();
+        let size = (pc + 1 < memory.len())
+            .then(|| u16::from_be_bytes([memory[pc], memory[pc + 1]]))
+            .and_then(|opcode| Instruction::decode(opcode).ok())
+            .map_or(2, |instruction| instruction.size());
+        count(&mut self.executed, pc..pc + size);
+        self.starts.insert(pc);
+        for access in machine.memory_accesses() {
+            let counts = match access.kind {
+                AccessKind::Read => &mut self.reads,
+                AccessKind::Write => &mut self.writes,
+            };
+            count(counts, access.range.clone());
+        }
+    }
+
+    pub fn executions(&self, address: usize) -> u32 {
+        self.executed.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn reads(&self, address: usize) -> u32 {
+        self.reads.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u32 {
+        self.writes.get(address).copied().unwrap_or(0)
+    }
+
+    /// The most any byte was executed
+    pub fn max_executions(&self) -> u32 {
+        self.executed.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The instructions [`Analysis`] can reach in the rom, that never ran
+    pub fn never_executed(&self, rom: &[u8]) -> Vec<usize> {
+        Analysis::new(rom)
+            .instructions
+            .into_keys()
+            .filter(|address| !self.starts.contains(address))
+            .collect()
+    }
+
+    /// The coverage of a rom loaded at [`ORIGIN`], ranges are `[start, end]` with the end excluded:
+    /// ```text
+    /// {
+    ///   "rom": [512, 812],
+    ///   "instructions": {"reachable": 120, "executed": 110, "never_executed": [530, 540]},
+    ///   "executed": [[512, 640], [644, 812]],
+    ///   "read": [[80, 85], [700, 715]],
+    ///   "written": [[3584, 3587]]
+    /// }
+    /// ```
+    pub fn json(&self, rom: &[u8]) -> String {
+        let reachable = Analysis::new(rom).instructions.len();
+        let executed = self.starts.range(ORIGIN..ORIGIN + rom.len()).count();
+        let never_executed: Vec<_> = self
+            .never_executed(rom)
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+        let ranges = |counts: &[u32]| {
+            ranges(counts)
+                .iter()
+                .map(|range| format!("[{}, {}]", range.start, range.end))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let fields = [
+            format!("\"rom\": [{ORIGIN}, {}]", ORIGIN + rom.len()),
+            format!(
+                "\"instructions\": {{\"reachable\": {reachable}, \"executed\": {executed}, \"never_executed\": [{}]}}",
+                never_executed.join(", ")
+            ),
+            format!("\"executed\": [{}]", ranges(&self.executed)),
+            format!("\"read\": [{}]", ranges(&self.reads)),
+            format!("\"written\": [{}]", ranges(&self.writes)),
+        ];
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+
+    /// The disassembly of the rom, each instruction with how many times it ran, `#####` for never.
+    /// Data lines get an `r` if any of their bytes was read, a `w` if written, an `x` if executed,
+    /// which is also how code that was only written at runtime shows up
+    pub fn listing(&self, rom: &[u8], syntax: Syntax, symbols: &Symbols) -> String {
+        let mut starts: BTreeSet<_> = Analysis::new(rom).instructions.into_keys().collect();
+        // the rom bytes of what ran may not decode, if the code was modified before running
+        starts.extend(
+            self.starts
+                .range(ORIGIN..ORIGIN + rom.len())
+                .filter(|&&address| instruction_at(rom, address).is_some()),
+        );
+        let note = |address: usize, len: usize| {
+            if starts.contains(&address) {
+                return match self.executions(address) {
+                    0 => format!("{:>8}", "#####"),
+                    count => format!("{count:>8}"),
+                };
+            }
+            let touched = |counts: &[u32]| {
+                (address..address + len).any(|a| counts.get(a).is_some_and(|&n| n > 0))
+            };
+            let marks: String = [(&self.reads, 'r'), (&self.writes, 'w'), (&self.executed, 'x')]
+                .into_iter()
+                .filter(|(counts, _)| touched(counts))
+                .map(|(_, mark)| mark)
+                .collect();
+            format!("{marks:>8}")
+        };
+        let comment = match syntax {
+            Syntax::Octo => '#',
+            Syntax::Classic => ';',
+        };
+        let executed = starts.iter().filter(|&&a| self.executions(a) > 0).count();
+        format!(
+            "{comment} {executed} of {} instructions executed\n{}",
+            starts.len(),
+            disasm::listing(rom, syntax, &starts, symbols, &note)
+        )
+    }
+}
+
+/// Adds one to the counts of `range`, growing them as needed
+fn count(counts: &mut Vec<u32>, range: Range<usize>) {
+    if counts.len() < range.end {
+        counts.resize(range.end, 0);
+    }
+    for count in &mut counts[range] {
+        *count = count.saturating_add(1);
+    }
+}
+
+/// The runs of addresses that were counted at least once
+fn ranges(counts: &[u32]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (address, _) in counts.iter().enumerate().filter(|&(_, &count)| count > 0) {
+        match ranges.last_mut() {
+            Some(last) if last.end == address => last.end += 1,
+            _ => ranges.push(address..address + 1),
+        }
+    }
+    ranges
+}
+
+use crate::analysis::Analysis;
+use crate::cpu::{Instruction, Syntax};
+use crate::disasm::{self, instruction_at, ORIGIN};
+use crate::memory::AccessKind;
+use crate::symbols::Symbols;
+use crate::Chip8;
+use std::collections::BTreeSet;
+use std::ops::Range;
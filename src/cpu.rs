@@ -1,124 +1,321 @@
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     /// Stores the return addresses
     stack: Vec<usize>,
     i_register: u16,
     register_memory: [u8; 16],
     memory: memory::Memory,
-    graphics_memory: Arc<RwLock<GraphicsMemory>>,
+    graphics_memory: GraphicsMemory,
     instruction_ptr: usize,
-    delay_timer: Arc<RwLock<BaseTimer>>,
+    delay_timer: BaseTimer,
+    sound_timer: BaseTimer,
+    audio: Audio,
     inputs: input::InpuState,
-    sound_timer: Sender<u8>
+    quirks: Quirks,
+    /// Used by `CXNN`
+    rng: Rng,
+    /// SUPER-CHIP's user flags, saved and loaded by `FX75`/`FX85`
+    rpl_flags: [u8; 16],
+    /// Set by SUPER-CHIP's `00FD`
+    exited: bool,
+    /// What the last instruction read/wrote, not part of the save states
+    memory_accesses: Vec<memory::MemoryAccess>,
 }
 
 impl CPU {
-    pub fn new(
-        file: std::fs::File,
-        graphics_memory: Arc<RwLock<GraphicsMemory>>,
-        delay_timer: Arc<RwLock<BaseTimer>>,
-        inputs: input::InpuState,
-        sound_timer: Sender<u8>
-    ) -> Result<Self, &'static str> {
+    /// `memory_size` is either [`memory::Memory::SIZE`] or [`memory::Memory::XO_CHIP_SIZE`]
+    pub fn new(rom: &[u8], quirks: Quirks, memory_size: usize) -> Result<Self, &'static str> {
         Ok(CPU {
             stack: Vec::new(),
             i_register: 0,
             register_memory: [0; 16],
-            memory: memory::Memory::load_instructions(file)?,
-            graphics_memory,
+            memory: memory::Memory::load_instructions(rom, memory_size)?,
+            graphics_memory: GraphicsMemory::new(),
             instruction_ptr: memory::Memory::INSTRUCTIONS_START_ADDRESS,
-            delay_timer,
-            inputs,
-            sound_timer
+            delay_timer: BaseTimer::new(),
+            sound_timer: BaseTimer::new(),
+            audio: Audio::new(),
+            inputs: input::InpuState::new(),
+            quirks,
+            rng: Rng::from_entropy(),
+            rpl_flags: [0; 16],
+            exited: false,
+            memory_accesses: Vec::new(),
         })
     }
 
-    #[rustfmt::skip]
-    pub fn run_with_pauses(&mut self) -> ! {
-        let mut cycles = 0u64;
-        loop {
-            let opcode = self.fetch_opcode();
-            pause(format!("Starting Cycle: {}, CPU state: {}", cycles, self.dump_without_memory(opcode)));
-            self.follow_isa(opcode);
-            cycles += 1;
+    /// Fetches the next opcode and executes it
+    ///
+    /// On an error, the instruction pointer is left on the faulting instruction
+    pub fn cycle(&mut self) -> Result<(), ExecutionError> {
+        let pc = self.instruction_ptr;
+        self.memory_accesses.clear();
+        let result = self.fetch_opcode().and_then(|opcode| {
+            Instruction::decode(opcode)
+                .map_err(|_| ExecutionErrorKind::UnknownOpcode)
+                .and_then(|instruction| self.execute(instruction))
+                .map_err(|kind| ExecutionError::new(pc, opcode, kind))
+        });
+        if result.is_err() {
+            self.instruction_ptr = pc;
         }
+        result
     }
 
-    #[rustfmt::skip]
-    pub fn run(&mut self) -> ! {
-        loop {
-            let opcode = self.fetch_opcode();
-            self.follow_isa(opcode);
-            std::thread::sleep(std::time::Duration::from_micros(1_000_000 / 500)); // 500Hz
-        }
+    /// Decrements both the timers, meant to be called at 60Hz
+    pub fn tick_timers(&mut self) {
+        self.delay_timer.decrement();
+        self.sound_timer.decrement();
     }
 
-    #[rustfmt::skip]
-    pub fn run_with_pauses_dump_mem(&mut self) -> ! {
-        let mut cycles = 0u64;
-        loop {
-            let opcode = self.fetch_opcode();
-            pause(format!("Starting Cycle: {}, CPU state: {}", cycles, self.dump(opcode)));
-            self.follow_isa(opcode);
-            cycles += 1;
-        }
+    fn fetch_opcode(&mut self) -> Result<u16, ExecutionError> {
+        let opcode = self.peek_opcode().ok_or_else(|| {
+            let address = self.instruction_ptr.max(self.memory.len());
+            ExecutionError::new(
+                self.instruction_ptr,
+                0,
+                ExecutionErrorKind::MemoryOutOfRange { address },
+            )
+        })?;
+        self.instruction_ptr += 2;
+        Ok(opcode)
     }
 
-    fn fetch_opcode(&mut self) -> u16 {
-        let opcode = (self.memory[self.instruction_ptr] as u16) << 8
-            | self.memory[self.instruction_ptr + 1] as u16;
-        self.instruction_ptr += 2;
-        opcode
+    /// The opcode that would be executed in the next cycle,
+    /// if the instruction pointer is still in the memory
+    pub fn peek_opcode(&self) -> Option<u16> {
+        if self.instruction_ptr + 1 >= self.memory.len() {
+            return None;
+        }
+        Some(
+            (self.memory[self.instruction_ptr] as u16) << 8
+                | self.memory[self.instruction_ptr + 1] as u16,
+        )
     }
 
-    fn dump(&self, opcode: impl Into<Option<u16>>) -> String {
+    pub fn dump(&self, opcode: impl Into<Option<u16>>) -> String {
         format!(
-            "CPU Dump:
-            Memory: {:?}
-            Stack: {:?}
-            IRegister 0x{:04X}
-            Registry Memory: {:?}
-            Current Opcode pointer: 0x{:04X}
-            Opcode: {:?}
-            ",
-            self.memory,
-            self.stack,
-            self.i_register,
-            self.register_memory,
-            self.instruction_ptr,
-            opcode.into().map(|x| format!("{:04X}", x))
+            "{}Memory:\n{}\n",
+            self.dump_without_memory(opcode),
+            memview::hex(&self.memory, 0..self.memory.len(), &|_| ' ')
         )
     }
 
-    fn dump_without_memory(&self, opcode: impl Into<Option<u16>>) -> String {
+    pub fn dump_without_memory(&self, opcode: impl Into<Option<u16>>) -> String {
         format!(
             "CPU Dump:
             Stack: {:?}
             IRegister 0x{:04X}
             Registry Memory: {:?}
             Current Opcode pointer: 0x{:04X}
-            Opcode: {:?}
+            Opcode: {}
             ",
             self.stack,
             self.i_register,
             self.register_memory,
             self.instruction_ptr,
-            opcode.into().map(|x| format!("{:04X}", x))
+            self.describe(opcode.into())
         )
     }
-}
 
-fn pause(msg: String) {
-    tracing::info!("{}", msg);
-    stdin().read(&mut [0, 0]).unwrap();
+    /// The opcode along with its assembly, for the dumps
+    fn describe(&self, opcode: Option<u16>) -> String {
+        let Some(opcode) = opcode else {
+            return "None".to_string();
+        };
+        let ptr = self.instruction_ptr + 2;
+        let long = (ptr + 1 < self.memory.len())
+            .then(|| u16::from_be_bytes([self.memory[ptr], self.memory[ptr + 1]]));
+        match Instruction::decode(opcode) {
+            Ok(instruction) => format!(
+                "{opcode:04X} {}",
+                instruction.format(Syntax::Classic, long, &|_| None)
+            ),
+            Err(_) => format!("{opcode:04X} (unknown)"),
+        }
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.register_memory
+    }
+
+    pub fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    /// `index` is 0x0 to 0xF
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.register_memory[index] = value;
+    }
+
+    pub fn set_i_register(&mut self, value: u16) {
+        self.i_register = value;
+    }
+
+    pub fn set_instruction_ptr(&mut self, address: usize) {
+        self.instruction_ptr = address;
+    }
+
+    pub fn memory(&self) -> &memory::Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut memory::Memory {
+        &mut self.memory
+    }
+
+    pub fn graphics_memory(&self) -> &GraphicsMemory {
+        &self.graphics_memory
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer.read()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer.read()
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer.set_timer(value);
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer.set_timer(value);
+    }
+
+    pub fn audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    pub fn inputs(&self) -> &input::InpuState {
+        &self.inputs
+    }
+
+    pub fn inputs_mut(&mut self) -> &mut input::InpuState {
+        &mut self.inputs
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; 16] {
+        &self.rpl_flags
+    }
+
+    pub(crate) fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+        self.rpl_flags = flags;
+    }
+
+    /// The memory read/written by the last instruction
+    pub fn memory_accesses(&self) -> &[memory::MemoryAccess] {
+        &self.memory_accesses
+    }
+
+    /// Whether the rom asked to exit the interpreter
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
 }
 
-use std::io::{stdin, Read};
-use std::sync::mpsc::Sender;
-use std::sync::{Arc, RwLock};
+impl Snapshot for CPU {
+    fn save(&self, out: &mut StateWriter) {
+        self.memory.save(out);
+        out.bytes(&self.register_memory);
+        out.u16(self.i_register);
+        out.u32(self.instruction_ptr as u32);
+        out.u8(self.stack.len() as u8);
+        for &address in &self.stack {
+            out.u32(address as u32);
+        }
+        self.delay_timer.save(out);
+        self.sound_timer.save(out);
+        self.audio.save(out);
+        self.graphics_memory.save(out);
+        self.inputs.save(out);
+        self.quirks.save(out);
+        self.rng.save(out);
+        out.bytes(&self.rpl_flags);
+        out.bool(self.exited);
+    }
 
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        let memory = memory::Memory::load(input)?;
+        let register_memory = input
+            .bytes()?
+            .try_into()
+            .map_err(|_| SaveStateError::Invalid("registers"))?;
+        let i_register = input.u16()?;
+        let instruction_ptr = input.u32()? as usize;
+        let stack_len = input.u8()? as usize;
+        let stack: Vec<_> = (0..stack_len)
+            .map(|_| input.u32().map(|address| address as usize))
+            .collect::<Result<_, _>>()?;
+        let cpu = CPU {
+            stack,
+            i_register,
+            register_memory,
+            memory,
+            instruction_ptr,
+            delay_timer: BaseTimer::load(input)?,
+            sound_timer: BaseTimer::load(input)?,
+            audio: Audio::load(input)?,
+            graphics_memory: GraphicsMemory::load(input)?,
+            inputs: input::InpuState::load(input)?,
+            quirks: Quirks::load(input)?,
+            rng: Rng::load(input)?,
+            rpl_flags: input
+                .bytes()?
+                .try_into()
+                .map_err(|_| SaveStateError::Invalid("user flags"))?,
+            exited: input.bool()?,
+            memory_accesses: Vec::new(),
+        };
+        if cpu.stack.len() > cpu.quirks.stack_depth {
+            return Err(SaveStateError::Invalid("stack depth"));
+        }
+        Ok(cpu)
+    }
+}
+
+use crate::audio::Audio;
 use crate::graphics::GraphicsMemory;
-use crate::{input, memory};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::timers::BaseTimer;
+use crate::{input, memory, memview};
+
+pub use error::{ExecutionError, ExecutionErrorKind};
+pub use instruction::{DecodeError, Instruction, Syntax};
+pub use undo::Undo;
 
-/// Has function for decoding and executing the opcodes
+/// Errors that halt the CPU
+mod error;
+/// The opcodes as a typed enum, and their assembly
+mod instruction;
+/// Executes the decoded instructions
 mod isa;
+/// Taking back an instruction, for stepping backwards
+mod undo;
@@ -0,0 +1,49 @@
+/// Why the CPU could not execute an instruction.
+///
+/// The CPU halts on these, instead of panicking, so that the frontend can
+/// show what happened and reset the machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionError {
+    /// Address of the instruction that faulted
+    pub pc: usize,
+    pub opcode: u16,
+    pub kind: ExecutionErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionErrorKind {
+    /// The opcode doesnt belong to the instruction set,
+    /// this includes `0NNN`, as we cannot run machine code routines
+    UnknownOpcode,
+    /// `00EE` with no function call to return to
+    StackUnderflow,
+    /// `2NNN` with the stack already holding [`Quirks::stack_depth`](crate::quirks::Quirks::stack_depth) return addresses
+    StackOverflow,
+    /// The instruction tried to touch memory outside of the memory
+    MemoryOutOfRange { address: usize },
+    /// `EX9E`/`EXA1` on a register that doesnt hold a key
+    InvalidKey(u8),
+}
+
+impl ExecutionError {
+    pub(crate) fn new(pc: usize, opcode: u16, kind: ExecutionErrorKind) -> Self {
+        ExecutionError { pc, opcode, kind }
+    }
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ExecutionErrorKind::UnknownOpcode => write!(f, "Unknown opcode")?,
+            ExecutionErrorKind::StackUnderflow => write!(f, "No function call to return to")?,
+            ExecutionErrorKind::StackOverflow => write!(f, "Too many nested function calls")?,
+            ExecutionErrorKind::MemoryOutOfRange { address } => {
+                write!(f, "Memory address 0x{:04X} is out of range", address)?
+            }
+            ExecutionErrorKind::InvalidKey(key) => write!(f, "0x{:02X} is not a key", key)?,
+        }
+        write!(f, ", opcode: {:04X}, at 0x{:04X}", self.opcode, self.pc)
+    }
+}
+
+impl std::error::Error for ExecutionError {}
@@ -0,0 +1,480 @@
+/// A decoded opcode, of CHIP-8 along with the SUPER-CHIP and XO-CHIP extensions.
+///
+/// `x`/`y` are the register indices, 0x0 to 0xF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `00CN`
+    ScrollDown(u8),
+    /// `00DN`
+    ScrollUp(u8),
+    /// `00E0`
+    ClearScreen,
+    /// `00EE`
+    Return,
+    /// `00FB`
+    ScrollRight,
+    /// `00FC`
+    ScrollLeft,
+    /// `00FD`
+    Exit,
+    /// `00FE`
+    LowRes,
+    /// `00FF`
+    HighRes,
+    /// `1NNN`
+    Jump(u16),
+    /// `2NNN`
+    Call(u16),
+    /// `3XNN`
+    SkipIfEqual { x: usize, value: u8 },
+    /// `4XNN`
+    SkipIfNotEqual { x: usize, value: u8 },
+    /// `5XY0`
+    SkipIfRegistersEqual { x: usize, y: usize },
+    /// `5XY2`, VX to VY into the memory at I
+    SaveRange { x: usize, y: usize },
+    /// `5XY3`, VX to VY from the memory at I
+    LoadRange { x: usize, y: usize },
+    /// `6XNN`
+    Load { x: usize, value: u8 },
+    /// `7XNN`
+    Add { x: usize, value: u8 },
+    /// `8XY0`
+    Copy { x: usize, y: usize },
+    /// `8XY1`
+    Or { x: usize, y: usize },
+    /// `8XY2`
+    And { x: usize, y: usize },
+    /// `8XY3`
+    Xor { x: usize, y: usize },
+    /// `8XY4`
+    AddRegisters { x: usize, y: usize },
+    /// `8XY5`, VX - VY
+    Sub { x: usize, y: usize },
+    /// `8XY6`
+    ShiftRight { x: usize, y: usize },
+    /// `8XY7`, VY - VX
+    SubReversed { x: usize, y: usize },
+    /// `8XYE`
+    ShiftLeft { x: usize, y: usize },
+    /// `9XY0`
+    SkipIfRegistersNotEqual { x: usize, y: usize },
+    /// `ANNN`
+    LoadI(u16),
+    /// `BNNN`, NNN + V0, or XNN + VX with the jump quirk
+    JumpOffset(u16),
+    /// `CXNN`
+    Random { x: usize, mask: u8 },
+    /// `DXYN`, a height of 0 is the 16x16 sprite
+    Draw { x: usize, y: usize, height: u8 },
+    /// `EX9E`
+    SkipIfKey { x: usize },
+    /// `EXA1`
+    SkipIfNotKey { x: usize },
+    /// `F000 NNNN`, the address is in the 2 bytes after the opcode
+    LoadILong,
+    /// `FN01`
+    SelectPlanes(u8),
+    /// `F002`
+    LoadAudio,
+    /// `FX07`
+    GetDelay { x: usize },
+    /// `FX0A`
+    WaitKey { x: usize },
+    /// `FX15`
+    SetDelay { x: usize },
+    /// `FX18`
+    SetSound { x: usize },
+    /// `FX1E`
+    AddI { x: usize },
+    /// `FX29`
+    Font { x: usize },
+    /// `FX30`
+    BigFont { x: usize },
+    /// `FX33`
+    Bcd { x: usize },
+    /// `FX3A`
+    SetPitch { x: usize },
+    /// `FX55`
+    Store { x: usize },
+    /// `FX65`
+    Restore { x: usize },
+    /// `FX75`
+    SaveFlags { x: usize },
+    /// `FX85`
+    LoadFlags { x: usize },
+}
+
+/// An opcode that [`Instruction::decode`] doesnt know
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+/// How an instruction is written down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// As in Octo, `v0 := 0x05`
+    Octo,
+    /// As in Cowgod's reference, `LD V0, 0x05`
+    Classic,
+}
+
+impl Instruction {
+    /// Fails on the opcodes that arent in any of the instruction sets,
+    /// which includes the machine code routines, `0NNN`
+    #[rustfmt::skip]
+    pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
+        use Instruction::*;
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        Ok(match (opcode >> 12, x, y, n) {
+            (0x0, 0, 0xC, _) => ScrollDown(n),
+            (0x0, 0, 0xD, _) => ScrollUp(n),
+            (0x0, 0, 0xE, 0x0) => ClearScreen,
+            (0x0, 0, 0xE, 0xE) => Return,
+            (0x0, 0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0, 0xF, 0xD) => Exit,
+            (0x0, 0, 0xF, 0xE) => LowRes,
+            (0x0, 0, 0xF, 0xF) => HighRes,
+            (0x1, ..) => Jump(nnn),
+            (0x2, ..) => Call(nnn),
+            (0x3, ..) => SkipIfEqual { x, value: nn },
+            (0x4, ..) => SkipIfNotEqual { x, value: nn },
+            (0x5, .., 0x0) => SkipIfRegistersEqual { x, y },
+            (0x5, .., 0x2) => SaveRange { x, y },
+            (0x5, .., 0x3) => LoadRange { x, y },
+            (0x6, ..) => Load { x, value: nn },
+            (0x7, ..) => Add { x, value: nn },
+            (0x8, .., 0x0) => Copy { x, y },
+            (0x8, .., 0x1) => Or { x, y },
+            (0x8, .., 0x2) => And { x, y },
+            (0x8, .., 0x3) => Xor { x, y },
+            (0x8, .., 0x4) => AddRegisters { x, y },
+            (0x8, .., 0x5) => Sub { x, y },
+            (0x8, .., 0x6) => ShiftRight { x, y },
+            (0x8, .., 0x7) => SubReversed { x, y },
+            (0x8, .., 0xE) => ShiftLeft { x, y },
+            (0x9, .., 0x0) => SkipIfRegistersNotEqual { x, y },
+            (0xA, ..) => LoadI(nnn),
+            (0xB, ..) => JumpOffset(nnn),
+            (0xC, ..) => Random { x, mask: nn },
+            (0xD, ..) => Draw { x, y, height: n },
+            (0xE, _, 0x9, 0xE) => SkipIfKey { x },
+            (0xE, _, 0xA, 0x1) => SkipIfNotKey { x },
+            (0xF, 0, 0x0, 0x0) => LoadILong,
+            (0xF, _, 0x0, 0x1) => SelectPlanes(x as u8),
+            (0xF, 0, 0x0, 0x2) => LoadAudio,
+            (0xF, _, 0x0, 0x7) => GetDelay { x },
+            (0xF, _, 0x0, 0xA) => WaitKey { x },
+            (0xF, _, 0x1, 0x5) => SetDelay { x },
+            (0xF, _, 0x1, 0x8) => SetSound { x },
+            (0xF, _, 0x1, 0xE) => AddI { x },
+            (0xF, _, 0x2, 0x9) => Font { x },
+            (0xF, _, 0x3, 0x0) => BigFont { x },
+            (0xF, _, 0x3, 0x3) => Bcd { x },
+            (0xF, _, 0x3, 0xA) => SetPitch { x },
+            (0xF, _, 0x5, 0x5) => Store { x },
+            (0xF, _, 0x6, 0x5) => Restore { x },
+            (0xF, _, 0x7, 0x5) => SaveFlags { x },
+            (0xF, _, 0x8, 0x5) => LoadFlags { x },
+            _ => return Err(DecodeError { opcode }),
+        })
+    }
+
+    /// The opcode it decodes from, `F000` without the address after it
+    #[rustfmt::skip]
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        let xy = |opcode: u16, x: usize, y: usize| opcode | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |opcode: u16, x: usize, nn: u8| opcode | (x as u16) << 8 | nn as u16;
+        match *self {
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipIfEqual { x, value } => xnn(0x3000, x, value),
+            SkipIfNotEqual { x, value } => xnn(0x4000, x, value),
+            SkipIfRegistersEqual { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            Load { x, value } => xnn(0x6000, x, value),
+            Add { x, value } => xnn(0x7000, x, value),
+            Copy { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            AddRegisters { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            ShiftRight { x, y } => xy(0x8006, x, y),
+            SubReversed { x, y } => xy(0x8007, x, y),
+            ShiftLeft { x, y } => xy(0x800E, x, y),
+            SkipIfRegistersNotEqual { x, y } => xy(0x9000, x, y),
+            LoadI(nnn) => 0xA000 | nnn,
+            JumpOffset(nnn) => 0xB000 | nnn,
+            Random { x, mask } => xnn(0xC000, x, mask),
+            Draw { x, y, height } => xy(0xD000, x, y) | height as u16,
+            SkipIfKey { x } => xnn(0xE09E, x, 0),
+            SkipIfNotKey { x } => xnn(0xE0A1, x, 0),
+            LoadILong => 0xF000,
+            SelectPlanes(n) => xnn(0xF001, n as usize, 0),
+            LoadAudio => 0xF002,
+            GetDelay { x } => xnn(0xF007, x, 0),
+            WaitKey { x } => xnn(0xF00A, x, 0),
+            SetDelay { x } => xnn(0xF015, x, 0),
+            SetSound { x } => xnn(0xF018, x, 0),
+            AddI { x } => xnn(0xF01E, x, 0),
+            Font { x } => xnn(0xF029, x, 0),
+            BigFont { x } => xnn(0xF030, x, 0),
+            Bcd { x } => xnn(0xF033, x, 0),
+            SetPitch { x } => xnn(0xF03A, x, 0),
+            Store { x } => xnn(0xF055, x, 0),
+            Restore { x } => xnn(0xF065, x, 0),
+            SaveFlags { x } => xnn(0xF075, x, 0),
+            LoadFlags { x } => xnn(0xF085, x, 0),
+        }
+    }
+
+    /// Number of bytes it takes in the memory, only `F000 NNNN` is 4
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadILong => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address it jumps or calls to, `BNNN` doesnt count as it depends on a register
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Instruction::Jump(address) | Instruction::Call(address) => Some(*address),
+            _ => None,
+        }
+    }
+
+    /// Whether it may skip the instruction after it
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            SkipIfEqual { .. }
+                | SkipIfNotEqual { .. }
+                | SkipIfRegistersEqual { .. }
+                | SkipIfRegistersNotEqual { .. }
+                | SkipIfKey { .. }
+                | SkipIfNotKey { .. }
+        )
+    }
+
+    /// The opcode it comes from, with the operands as letters, e.g. `DXYN` or `8XY4`
+    #[rustfmt::skip]
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+        match self {
+            ScrollDown(_) => "00CN",             ScrollUp(_) => "00DN",
+            ClearScreen => "00E0",               Return => "00EE",
+            ScrollRight => "00FB",               ScrollLeft => "00FC",
+            Exit => "00FD",                      LowRes => "00FE",
+            HighRes => "00FF",                   Jump(_) => "1NNN",
+            Call(_) => "2NNN",                   SkipIfEqual { .. } => "3XNN",
+            SkipIfNotEqual { .. } => "4XNN",     SkipIfRegistersEqual { .. } => "5XY0",
+            SaveRange { .. } => "5XY2",          LoadRange { .. } => "5XY3",
+            Load { .. } => "6XNN",               Add { .. } => "7XNN",
+            Copy { .. } => "8XY0",               Or { .. } => "8XY1",
+            And { .. } => "8XY2",                Xor { .. } => "8XY3",
+            AddRegisters { .. } => "8XY4",       Sub { .. } => "8XY5",
+            ShiftRight { .. } => "8XY6",         SubReversed { .. } => "8XY7",
+            ShiftLeft { .. } => "8XYE",          SkipIfRegistersNotEqual { .. } => "9XY0",
+            LoadI(_) => "ANNN",                  JumpOffset(_) => "BNNN",
+            Random { .. } => "CXNN",             Draw { .. } => "DXYN",
+            SkipIfKey { .. } => "EX9E",          SkipIfNotKey { .. } => "EXA1",
+            LoadILong => "F000",                 SelectPlanes(_) => "FN01",
+            LoadAudio => "F002",                 GetDelay { .. } => "FX07",
+            WaitKey { .. } => "FX0A",            SetDelay { .. } => "FX15",
+            SetSound { .. } => "FX18",           AddI { .. } => "FX1E",
+            Font { .. } => "FX29",               BigFont { .. } => "FX30",
+            Bcd { .. } => "FX33",                SetPitch { .. } => "FX3A",
+            Store { .. } => "FX55",              Restore { .. } => "FX65",
+            SaveFlags { .. } => "FX75",          LoadFlags { .. } => "FX85",
+        }
+    }
+
+    /// Writes the instruction down, `long` is the address after a `F000`,
+    /// `label` gives the names of the addresses, if they have one
+    #[rustfmt::skip]
+    pub fn format(&self, syntax: Syntax, long: Option<u16>, label: &dyn Fn(u16) -> Option<String>) -> String {
+        use Instruction::*;
+        let address = |address: u16| label(address).unwrap_or_else(|| format!("0x{address:03X}"));
+        let long = long.map_or("NNNN".to_string(), |long| label(long).unwrap_or_else(|| format!("0x{long:04X}")));
+        match syntax {
+            Syntax::Octo => match *self {
+                ScrollDown(n) => format!("scroll-down {n}"),
+                ScrollUp(n) => format!("scroll-up {n}"),
+                ClearScreen => "clear".to_string(),
+                Return => "return".to_string(),
+                ScrollRight => "scroll-right".to_string(),
+                ScrollLeft => "scroll-left".to_string(),
+                Exit => "exit".to_string(),
+                LowRes => "lores".to_string(),
+                HighRes => "hires".to_string(),
+                Jump(nnn) => format!("jump {}", address(nnn)),
+                Call(nnn) => format!(":call {}", address(nnn)),
+                // the skips are the opposite of the conditions of Octo's `if ... then`
+                SkipIfEqual { x, value } => format!("if v{x:x} != 0x{value:02X} then"),
+                SkipIfNotEqual { x, value } => format!("if v{x:x} == 0x{value:02X} then"),
+                SkipIfRegistersEqual { x, y } => format!("if v{x:x} != v{y:x} then"),
+                SaveRange { x, y } => format!("save v{x:x} - v{y:x}"),
+                LoadRange { x, y } => format!("load v{x:x} - v{y:x}"),
+                Load { x, value } => format!("v{x:x} := 0x{value:02X}"),
+                Add { x, value } => format!("v{x:x} += 0x{value:02X}"),
+                Copy { x, y } => format!("v{x:x} := v{y:x}"),
+                Or { x, y } => format!("v{x:x} |= v{y:x}"),
+                And { x, y } => format!("v{x:x} &= v{y:x}"),
+                Xor { x, y } => format!("v{x:x} ^= v{y:x}"),
+                AddRegisters { x, y } => format!("v{x:x} += v{y:x}"),
+                Sub { x, y } => format!("v{x:x} -= v{y:x}"),
+                ShiftRight { x, y } => format!("v{x:x} >>= v{y:x}"),
+                SubReversed { x, y } => format!("v{x:x} =- v{y:x}"),
+                ShiftLeft { x, y } => format!("v{x:x} <<= v{y:x}"),
+                SkipIfRegistersNotEqual { x, y } => format!("if v{x:x} == v{y:x} then"),
+                LoadI(nnn) => format!("i := {}", address(nnn)),
+                JumpOffset(nnn) => format!("jump0 {}", address(nnn)),
+                Random { x, mask } => format!("v{x:x} := random 0x{mask:02X}"),
+                Draw { x, y, height } => format!("sprite v{x:x} v{y:x} {height}"),
+                SkipIfKey { x } => format!("if v{x:x} -key then"),
+                SkipIfNotKey { x } => format!("if v{x:x} key then"),
+                LoadILong => format!("i := long {long}"),
+                SelectPlanes(n) => format!("plane {n}"),
+                LoadAudio => "audio".to_string(),
+                GetDelay { x } => format!("v{x:x} := delay"),
+                WaitKey { x } => format!("v{x:x} := key"),
+                SetDelay { x } => format!("delay := v{x:x}"),
+                SetSound { x } => format!("buzzer := v{x:x}"),
+                AddI { x } => format!("i += v{x:x}"),
+                Font { x } => format!("i := hex v{x:x}"),
+                BigFont { x } => format!("i := bighex v{x:x}"),
+                Bcd { x } => format!("bcd v{x:x}"),
+                SetPitch { x } => format!("pitch := v{x:x}"),
+                Store { x } => format!("save v{x:x}"),
+                Restore { x } => format!("load v{x:x}"),
+                SaveFlags { x } => format!("saveflags v{x:x}"),
+                LoadFlags { x } => format!("loadflags v{x:x}"),
+            },
+            Syntax::Classic => match *self {
+                ScrollDown(n) => format!("SCD {n}"),
+                ScrollUp(n) => format!("SCU {n}"),
+                ClearScreen => "CLS".to_string(),
+                Return => "RET".to_string(),
+                ScrollRight => "SCR".to_string(),
+                ScrollLeft => "SCL".to_string(),
+                Exit => "EXIT".to_string(),
+                LowRes => "LOW".to_string(),
+                HighRes => "HIGH".to_string(),
+                Jump(nnn) => format!("JP {}", address(nnn)),
+                Call(nnn) => format!("CALL {}", address(nnn)),
+                SkipIfEqual { x, value } => format!("SE V{x:X}, 0x{value:02X}"),
+                SkipIfNotEqual { x, value } => format!("SNE V{x:X}, 0x{value:02X}"),
+                SkipIfRegistersEqual { x, y } => format!("SE V{x:X}, V{y:X}"),
+                SaveRange { x, y } => format!("SAVE V{x:X}-V{y:X}"),
+                LoadRange { x, y } => format!("LOAD V{x:X}-V{y:X}"),
+                Load { x, value } => format!("LD V{x:X}, 0x{value:02X}"),
+                Add { x, value } => format!("ADD V{x:X}, 0x{value:02X}"),
+                Copy { x, y } => format!("LD V{x:X}, V{y:X}"),
+                Or { x, y } => format!("OR V{x:X}, V{y:X}"),
+                And { x, y } => format!("AND V{x:X}, V{y:X}"),
+                Xor { x, y } => format!("XOR V{x:X}, V{y:X}"),
+                AddRegisters { x, y } => format!("ADD V{x:X}, V{y:X}"),
+                Sub { x, y } => format!("SUB V{x:X}, V{y:X}"),
+                ShiftRight { x, y } => format!("SHR V{x:X}, V{y:X}"),
+                SubReversed { x, y } => format!("SUBN V{x:X}, V{y:X}"),
+                ShiftLeft { x, y } => format!("SHL V{x:X}, V{y:X}"),
+                SkipIfRegistersNotEqual { x, y } => format!("SNE V{x:X}, V{y:X}"),
+                LoadI(nnn) => format!("LD I, {}", address(nnn)),
+                JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+                Random { x, mask } => format!("RND V{x:X}, 0x{mask:02X}"),
+                Draw { x, y, height } => format!("DRW V{x:X}, V{y:X}, {height}"),
+                SkipIfKey { x } => format!("SKP V{x:X}"),
+                SkipIfNotKey { x } => format!("SKNP V{x:X}"),
+                LoadILong => format!("LD I, {long}"),
+                SelectPlanes(n) => format!("PLANE {n}"),
+                LoadAudio => "AUDIO".to_string(),
+                GetDelay { x } => format!("LD V{x:X}, DT"),
+                WaitKey { x } => format!("LD V{x:X}, K"),
+                SetDelay { x } => format!("LD DT, V{x:X}"),
+                SetSound { x } => format!("LD ST, V{x:X}"),
+                AddI { x } => format!("ADD I, V{x:X}"),
+                Font { x } => format!("LD F, V{x:X}"),
+                BigFont { x } => format!("LD HF, V{x:X}"),
+                Bcd { x } => format!("LD B, V{x:X}"),
+                SetPitch { x } => format!("PITCH V{x:X}"),
+                Store { x } => format!("LD [I], V{x:X}"),
+                Restore { x } => format!("LD V{x:X}, [I]"),
+                SaveFlags { x } => format!("LD R, V{x:X}"),
+                LoadFlags { x } => format!("LD V{x:X}, R"),
+            },
+        }
+    }
+}
+
+/// The classic syntax, without any labels
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(Syntax::Classic, None, &|_| None))
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use std::collections::HashMap;
+
+    #[test]
+    fn every_opcode_round_trips() {
+        let mut classic = HashMap::new();
+        for opcode in 0..=0xFFFF_u16 {
+            let instruction = match Instruction::decode(opcode) {
+                Ok(instruction) => instruction,
+                Err(error) => {
+                    assert_eq!(error, DecodeError { opcode });
+                    continue;
+                }
+            };
+            assert_eq!(instruction.encode(), opcode, "{instruction:?}");
+
+            // the Octo syntax assembles back into the same bytes
+            let long = (instruction == Instruction::LoadILong).then_some(0x1234);
+            let octo = instruction.format(Syntax::Octo, long, &|_| None);
+            let mut expected = opcode.to_be_bytes().to_vec();
+            if let Some(long) = long {
+                expected.extend(long.to_be_bytes());
+            }
+            let rom = assemble(&format!(": main {octo}"));
+            assert_eq!(rom, Ok(expected), "{opcode:04X} `{octo}`");
+
+            // and the classic syntax tells every opcode apart
+            let text = instruction.to_string();
+            if let Some(other) = classic.insert(text.clone(), opcode) {
+                panic!("{other:04X} and {opcode:04X} are both `{text}`");
+            }
+        }
+    }
+}
@@ -1,214 +1,315 @@
 impl super::CPU {
     /// completely inspired from https://github.com/mattmikolay/chip-8/wiki/CHIP%E2%80%908-Instruction-Set
-    pub fn follow_isa(&mut self, opcode: u16) {
-        let register_x = ((opcode & 0x0F00) / 0x100) as usize;
-        let register_y = ((opcode & 0x00F0) / 0x10) as usize;
-        match opcode {
-            0x00E0 => self.graphics_memory.write().unwrap().clear_screen(),
+    ///
+    /// Expects the instruction pointer to be already moved past the opcode.
+    #[rustfmt::skip]
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorKind> {
+        match instruction {
+            ScrollDown(n) => self.graphics_memory.scroll_down(n as usize),
+            ScrollUp(n) => self.graphics_memory.scroll_up(n as usize),
+            ClearScreen => self.graphics_memory.clear_screen(),
+            ScrollRight => self.graphics_memory.scroll_right(4),
+            ScrollLeft => self.graphics_memory.scroll_left(4),
+            Exit => self.exited = true,
+            LowRes => self.graphics_memory.set_hires(false),
+            HighRes => self.graphics_memory.set_hires(true),
 
-            #[rustfmt::skip]
-            0x00EE => self.instruction_ptr = self.stack.pop().expect(&format!("No function call to return to, cpu state: {}", self.dump(opcode))),
+            Return => match self.stack.pop() {
+                Some(return_address) => self.instruction_ptr = return_address,
+                None => return Err(StackUnderflow),
+            },
+
+            Jump(address) => self.instruction_ptr = address as usize,
 
-            0..=0x0FFF => {
-                tracing::error!("I dont understand this opcode, {}", opcode);
+            Call(address) => {
+                if self.stack.len() >= self.quirks.stack_depth {
+                    return Err(StackOverflow);
+                }
                 self.stack.push(self.instruction_ptr);
-                self.instruction_ptr = opcode as usize;
+                self.instruction_ptr = address as usize;
             }
 
-            0x1000..=0x1FFF => self.instruction_ptr = (opcode - 0x1000) as usize,
-
-            0x2000..=0x2FFF => {
-                self.stack.push(self.instruction_ptr);
-                self.instruction_ptr = (opcode - 0x2000) as usize;
+            SkipIfEqual { x, value } => {
+                if self.register_memory[x] == value { self.skip_next_instruction(); }
+            }
+            SkipIfNotEqual { x, value } => {
+                if self.register_memory[x] != value { self.skip_next_instruction(); }
+            }
+            SkipIfRegistersEqual { x, y } => {
+                if self.register_memory[x] == self.register_memory[y] { self.skip_next_instruction(); }
+            }
+            SkipIfRegistersNotEqual { x, y } => {
+                if self.register_memory[x] != self.register_memory[y] { self.skip_next_instruction(); }
             }
 
-            0x3000..=0x3FFF => {
-                let value = self.register_memory[register_x];
-                let comparing_value = opcode & 0x00FF;
-                if value == comparing_value as u8 {
-                    self.instruction_ptr += 2;
+            // XO-CHIP, saves VX to VY at I, in either order, I is left as it is
+            SaveRange { x, y } => {
+                let registers = Self::register_range(x, y);
+                let range = self.access_memory(Write, self.i_register as usize, registers.len())?;
+                for (address, register) in range.zip(registers) {
+                    self.memory[address] = self.register_memory[register];
                 }
             }
-
-            0x4000..=0x4FFF => {
-                let value = self.register_memory[register_x];
-                let comparing_value = opcode & 0x00FF;
-                if value != comparing_value as u8 {
-                    self.instruction_ptr += 2;
+            // XO-CHIP, loads VX to VY from I
+            LoadRange { x, y } => {
+                let registers = Self::register_range(x, y);
+                let range = self.access_memory(Read, self.i_register as usize, registers.len())?;
+                for (address, register) in range.zip(registers) {
+                    self.register_memory[register] = self.memory[address];
                 }
             }
 
-            #[rustfmt::skip]
-            0x5000..=0x5FFF => {
-                if opcode & 0x000F == 0 {
-                    let v1 = self.register_memory[register_x];
-                    let v2 = self.register_memory[register_y];
-                    if v1 == v2 { self.instruction_ptr += 2; }
-                } else { unreachable!("Unknown opcode, cpu state: {}", self.dump(opcode)) }
-            },
+            Load { x, value } => self.register_memory[x] = value,
+            Add { x, value } => self.register_memory[x] = self.register_memory[x].wrapping_add(value),
 
-            0x6000..=0x6FFF => {
-                let value = opcode & 0x00FF;
-                self.register_memory[register_x] = value as u8;
+            Copy { x, y } => self.register_memory[x] = self.register_memory[y],
+            Or { x, y } => {
+                self.register_memory[x] |= self.register_memory[y];
+                self.vf_reset();
+            }
+            And { x, y } => {
+                self.register_memory[x] &= self.register_memory[y];
+                self.vf_reset();
+            }
+            Xor { x, y } => {
+                self.register_memory[x] ^= self.register_memory[y];
+                self.vf_reset();
+            }
+            AddRegisters { x, y } => {
+                let (result, overflow) = self.register_memory[x].overflowing_add(self.register_memory[y]);
+                self.register_memory[x] = result;
+                self.register_memory[0xF] = overflow as u8;
+            }
+            Sub { x, y } => {
+                let (result, overflow) = self.register_memory[x].overflowing_sub(self.register_memory[y]);
+                self.register_memory[x] = result;
+                self.register_memory[0xF] = !overflow as u8;
             }
+            ShiftRight { x, y } => {
+                let value = self.shift_operand(x, y);
+                self.register_memory[x] = value >> 1;
+                self.register_memory[0xF] = value & 0x1;
+            }
+            SubReversed { x, y } => {
+                let (result, overflow) = self.register_memory[y].overflowing_sub(self.register_memory[x]);
+                self.register_memory[x] = result;
+                self.register_memory[0xF] = !overflow as u8;
+            }
+            ShiftLeft { x, y } => {
+                let value = self.shift_operand(x, y);
+                self.register_memory[x] = value << 1;
+                self.register_memory[0xF] = value >> 7;
+            }
+
+            LoadI(address) => self.i_register = address,
 
-            0x7000..=0x7FFF => {
-                let value = (opcode & 0x00FF) as u8;
-                let reg_mem = &mut self.register_memory[register_x];
-                *reg_mem = reg_mem.wrapping_add(value);
+            JumpOffset(address) => {
+                let x = (address >> 8) as usize;
+                let offset = if self.quirks.jump_uses_vx { self.register_memory[x] } else { self.register_memory[0] };
+                self.instruction_ptr = (address + offset as u16) as usize % 0x10000;
             }
 
-            0x8000..=0x8FFF => match opcode & 0x000F {
-                0x0 => self.register_memory[register_x] = self.register_memory[register_y],
-                0x1 => {
-                    self.register_memory[register_x] |= self.register_memory[register_y];
-                    self.register_memory[0xF] = 0;
-                }
-                0x2 => {
-                    self.register_memory[register_x] &= self.register_memory[register_y];
-                    self.register_memory[0xF] = 0;
-                }
-                0x3 => {
-                    self.register_memory[register_x] ^= self.register_memory[register_y];
-                    self.register_memory[0xF] = 0;
-                }
-                0x4 => {
-                    let (result, overflow) = self.register_memory[register_x]
-                        .overflowing_add(self.register_memory[register_y]);
-                    self.register_memory[register_x] = result;
-                    self.register_memory[0xF] = overflow as u8;
-                }
-                0x5 => {
-                    let (result, overflow) = self.register_memory[register_x]
-                        .overflowing_sub(self.register_memory[register_y]);
-                    self.register_memory[register_x] = result;
-                    self.register_memory[0xF] = !overflow as u8;
-                }
-                0x6 => {
-                    self.register_memory[0xF] = self.register_memory[register_y] & 0x1;
-                    self.register_memory[register_x] = self.register_memory[register_y] >> 1;
-                }
-                0x7 => {
-                    let (result, overflow) = self.register_memory[register_y]
-                        .overflowing_sub(self.register_memory[register_x]);
-                    self.register_memory[register_x] = result;
-                    self.register_memory[0xF] = !overflow as u8;
-                }
-                0xE => {
-                    self.register_memory[0xF] = self.register_memory[register_y] & 0x80;
-                    self.register_memory[register_x] = self.register_memory[register_y] << 1;
-                }
+            Random { x, mask } => self.register_memory[x] = self.rng.next_u8() & mask,
+
+            Draw { x, y, height } => {
+                // DXY0 draws a 16x16 sprite
+                let (width, bytes) = match height as usize {
+                    0 => (16, 32),
+                    n => (8, n),
+                };
+                // one sprite for each of the selected XO-CHIP planes
+                let bytes = self.graphics_memory.sprite_len(bytes);
+                let range = self.access_memory(Read, self.i_register as usize, bytes)?;
+                self.register_memory[0xF] = self.graphics_memory.display_sprite(
+                    self.register_memory[x],
+                    self.register_memory[y],
+                    &self.memory[range],
+                    width,
+                    self.quirks.clipping,
+                ) as u8;
+            }
+
+            SkipIfKey { x } => {
+                if self.inputs.is_pressed(self.key_in(x)?) { self.skip_next_instruction(); }
+            }
+            SkipIfNotKey { x } => {
+                if !self.inputs.is_pressed(self.key_in(x)?) { self.skip_next_instruction(); }
+            }
+
+            // XO-CHIP's `F000 NNNN`, loads the 16 bit address that follows into I
+            LoadILong => {
+                let range = self.memory_range(self.instruction_ptr, 2)?;
+                let address = &self.memory[range];
+                self.i_register = u16::from_be_bytes([address[0], address[1]]);
+                self.instruction_ptr += 2;
+            }
+            // XO-CHIP, selects the planes to draw on
+            SelectPlanes(planes) => self.graphics_memory.select_planes(planes),
+            // XO-CHIP, loads the audio pattern from I
+            LoadAudio => {
+                let range = self.access_memory(Read, self.i_register as usize, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[range]);
+                self.audio.set_pattern(pattern);
+            }
 
-                _ => unreachable!("Unknown opcode, cpu state: {}", self.dump(opcode)),
+            GetDelay { x } => self.register_memory[x] = self.delay_timer.read(),
+            WaitKey { x } => match self.inputs.wait_for_key() {
+                Some(key) => self.register_memory[x] = key.into(),
+                // keep on executing this instruction, till a key gets pressed
+                None => self.instruction_ptr -= 2,
             },
+            SetDelay { x } => self.delay_timer.set_timer(self.register_memory[x]),
+            SetSound { x } => self.sound_timer.set_timer(self.register_memory[x]),
 
-            #[rustfmt::skip]
-            0x9000..=0x9FFF => {
-                if opcode & 0x000F == 0 {
-                    let v1 = self.register_memory[register_x];
-                    let v2 = self.register_memory[register_y];
-                    if v1 != v2 { self.instruction_ptr += 2; }
-                } else { unreachable!("Unknown opcode, cpu state: {}", self.dump(opcode)) }
+            AddI { x } => self.i_register = self.i_register.wrapping_add(self.register_memory[x] as u16),
+
+            Font { x } => {
+                let digit = self.register_memory[x];
+                let digit = if digit > 0xF {
+                    tracing::warn!("Trying to get a digit font for a non digit value: {}", digit);
+                    digit & 0xF
+                } else {digit};
+                self.i_register = memory::Memory::get_digit_address(digit) as u16;
             },
 
-            0xA000..=0xAFFF => self.i_register = opcode & 0x0FFF,
+            BigFont { x } => {
+                let digit = self.register_memory[x];
+                let digit = if digit > 0xF {
+                    tracing::warn!("Trying to get a big digit font for a non digit value: {}", digit);
+                    digit & 0xF
+                } else {digit};
+                self.i_register = memory::Memory::get_big_digit_address(digit) as u16;
+            },
 
-            #[rustfmt::skip]
-            0xB000..=0xBFFF => {
-                self.instruction_ptr = ((opcode & 0x0FFF) + self.register_memory[0] as u16) as usize % 0x10000;
-            }
+            // XO-CHIP, the pitch of the audio pattern
+            SetPitch { x } => self.audio.set_pitch(self.register_memory[x]),
 
-            0xC000..=0xCFFF => {
-                let random_number = rand::random::<u8>();
-                self.register_memory[register_x] = random_number & (opcode & 0x00FF) as u8;
+            Bcd { x } => {
+                let range = self.access_memory(Write, self.i_register as usize, 3)?;
+                let slice = &mut self.memory[range];
+                let val = self.register_memory[x];
+                let (hundreds, tens, ones) = (val / 100, (val % 100) / 10, val % 10);
+                slice.copy_from_slice(&[hundreds, tens, ones]);
             }
 
-            0xD000..=0xDFFF => {
-                self.register_memory[0xF] = self.graphics_memory.write().unwrap().display_sprite(
-                    self.register_memory[register_x],
-                    self.register_memory[register_y],
-                    {
-                        let n = (opcode & 0x000F) as usize;
-                        let start_idx = self.i_register as usize;
-                        &self.memory[start_idx..start_idx + n]
-                    },
-                ) as u8;
+            Store { x } => {
+                let range = self.access_memory(Write, self.i_register as usize, x + 1)?;
+                let slice = &mut self.memory[range];
+                slice.copy_from_slice(&self.register_memory[..=x]);
+                self.increment_i_after_load_store(x);
             }
 
-            0xE00..=0xEFFF => {
-                let function = (opcode & 0x00FF) as u8;
-                match function {
-                    0x9E => {
-                        self.inputs.receive_keys();
-                        if let Ok(key) = self.register_memory[register_x].try_into() {
-                            if self.inputs.is_pressed(key) {
-                                self.instruction_ptr += 2;
-                            }
-                        } else {
-                            // error already logged in the try_into implementation
-                        }
-                    }
-                    0xA1 => {
-                        self.inputs.receive_keys();
-                        if let Ok(key) = self.register_memory[register_x].try_into() {
-                            if !self.inputs.is_pressed(key) {
-                                self.instruction_ptr += 2;
-                            }
-                        } else {
-                            // error already logged in the try_into implementation
-                        }
-                    }
-                    _ => unreachable!("Unknown opcode, cpu state: {}", self.dump(opcode)),
-                }
+            Restore { x } => {
+                let range = self.access_memory(Read, self.i_register as usize, x + 1)?;
+                let slice = &self.memory[range];
+                self.register_memory[..=x].copy_from_slice(slice);
+                self.increment_i_after_load_store(x);
             }
 
-            #[rustfmt::skip]
-            0xF00..=0xFFFF => {
-                let function = (opcode & 0x00FF) as u8;
-                match function {
-                    0x07 => self.register_memory[register_x] = self.delay_timer.read().unwrap().read(),
-                    0x0A => self.register_memory[register_x] = self.inputs.wait_for_key().into(),
-                    0x15 => self.delay_timer.write().unwrap().set_timer(self.register_memory[register_x]),
-                    0x18 => self.sound_timer.send(self.register_memory[register_x]).unwrap(),
-
-                    0x1E => self.i_register = self.i_register.wrapping_add(self.register_memory[register_x] as u16),
-
-                    0x29 => {
-                        let digit = self.register_memory[register_x];
-                        let digit = if digit > 0xF {
-                            tracing::warn!("Trying to get a digit font for a non digit value: {}", digit);
-                            digit % 0xF
-                        } else {digit};
-                        self.i_register = memory::Memory::get_digit_address(digit) as u16;
-                    },
-
-                    0x33 => {
-                        let addr = self.i_register as usize;
-                        let slice = &mut self.memory[addr..addr + 3];
-                        let val = self.register_memory[register_x];
-                        let (hundreds, tens, ones) = (val / 100, (val % 100) / 10, val % 10);
-                        slice.copy_from_slice(&[hundreds, tens, ones]);
-                    }
-
-                    0x55 => {
-                        let addr = self.i_register as usize;
-                        let slice = &mut self.memory[addr..addr + register_x + 1];
-                        slice.copy_from_slice(&self.register_memory[..=register_x]);
-                        self.i_register += register_x as u16 + 1;
-                    }
-
-                    0x65 => {
-                        let addr = self.i_register as usize;
-                        let slice = &self.memory[addr..addr + register_x + 1];
-                        self.register_memory[..=register_x].copy_from_slice(slice);
-                        self.i_register += register_x as u16 + 1;
-                    }
-
-                    _ => unreachable!("Unknown opcode, cpu state: {}", self.dump(opcode)),
-                }
-            }
+            SaveFlags { x } => self.rpl_flags[..=x].copy_from_slice(&self.register_memory[..=x]),
+            LoadFlags { x } => self.register_memory[..=x].copy_from_slice(&self.rpl_flags[..=x]),
+        }
+        Ok(())
+    }
+
+    /// The logic ops of the original interpreter clobber VF
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.register_memory[0xF] = 0;
+        }
+    }
+
+    /// The key held in VX, for `EX9E`/`EXA1`
+    fn key_in(&self, register_x: usize) -> Result<Key, ExecutionErrorKind> {
+        let value = self.register_memory[register_x];
+        value.try_into().map_err(|_| InvalidKey(value))
+    }
+
+    /// Skips over the next instruction,
+    /// which might be the 4 byte long `F000 NNNN` of XO-CHIP
+    fn skip_next_instruction(&mut self) {
+        let long = self.peek_opcode() == Some(0xF000);
+        self.instruction_ptr += if long { 4 } else { 2 };
+    }
+
+    /// VX to VY, counting down if X > Y
+    fn register_range(register_x: usize, register_y: usize) -> Vec<usize> {
+        if register_x <= register_y {
+            (register_x..=register_y).collect()
+        } else {
+            (register_y..=register_x).rev().collect()
         }
     }
+
+    /// The value that gets shifted by `8XY6`/`8XYE`
+    fn shift_operand(&self, register_x: usize, register_y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.register_memory[register_y]
+        } else {
+            self.register_memory[register_x]
+        }
+    }
+
+    fn increment_i_after_load_store(&mut self, register_x: usize) {
+        match self.quirks.load_store {
+            LoadStore::IncrementByXPlusOne => self.i_register = self.i_register.wrapping_add(register_x as u16 + 1),
+            LoadStore::IncrementByX => self.i_register = self.i_register.wrapping_add(register_x as u16),
+            LoadStore::Unchanged => {}
+        }
+    }
+
+    /// Same as [`Self::memory_range`], but also records the access, for the debuggers
+    fn access_memory(
+        &mut self,
+        kind: AccessKind,
+        start: usize,
+        len: usize,
+    ) -> Result<Range<usize>, ExecutionErrorKind> {
+        let range = self.memory_range(start, len)?;
+        self.memory_accesses.push(MemoryAccess {
+            kind,
+            range: range.clone(),
+        });
+        Ok(range)
+    }
+
+    /// The range of `len` bytes starting at `start`, if all of them are in the memory
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, ExecutionErrorKind> {
+        if start + len > self.memory.len() {
+            return Err(MemoryOutOfRange {
+                address: start.max(self.memory.len()),
+            });
+        }
+        Ok(start..start + len)
+    }
 }
 
-use crate::memory;
+use std::ops::Range;
+
+use super::error::{ExecutionErrorKind, ExecutionErrorKind::*};
+use super::instruction::{Instruction, Instruction::*};
+use crate::input::Key;
+use crate::memory::{self, AccessKind, AccessKind::*, MemoryAccess};
+use crate::quirks::LoadStore;
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::CPU;
+    use crate::memory::Memory;
+    use crate::quirks::Quirks;
+    use crate::rng::Rng;
+
+    #[test]
+    fn random_masks_the_scripted_numbers() {
+        // v0 := random 0xFF, v1 := random 0x0F, v2 := random 0xF0, v3 := random 0xFF
+        let rom = [0xC0, 0xFF, 0xC1, 0x0F, 0xC2, 0xF0, 0xC3, 0xFF];
+        let mut cpu = CPU::new(&rom, Quirks::default(), Memory::SIZE).unwrap();
+        cpu.set_rng(Rng::scripted(vec![0xAB, 0x5C, 0x37]));
+        for _ in 0..4 {
+            cpu.cycle().unwrap();
+        }
+        // the fourth number loops back around to the first
+        assert_eq!(cpu.registers()[..4], [0xAB, 0x0C, 0x30, 0xAB]);
+    }
+}
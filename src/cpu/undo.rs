@@ -0,0 +1,153 @@
+/// What an instruction changed, enough to take it back with [`CPU::undo`].
+///
+/// The registers, I, PC and the timers are always kept. The stack, memory, screen,
+/// random numbers, audio and user flags only when the instruction can change them,
+/// and for the memory and the screen only the bytes/pixels that did change.
+/// The keypad isnt part of it, those are the keys the user is holding.
+#[derive(Debug, Clone)]
+pub struct Undo {
+    registers: [u8; 16],
+    i_register: u16,
+    instruction_ptr: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    exited: bool,
+    /// Changed by `2NNN` and `00EE`
+    stack: Option<Vec<usize>>,
+    /// The old values of the bytes that changed
+    memory: Vec<(usize, u8)>,
+    screen: Option<ScreenUndo>,
+    /// Changed by `CXNN`
+    rng: Option<Rng>,
+    /// Changed by `F002` and `FX3A`
+    audio: Option<Audio>,
+    /// Changed by `FX75`
+    rpl_flags: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone)]
+struct ScreenUndo {
+    hires: bool,
+    selected_planes: u8,
+    /// The old values of the pixels that changed, all the lit ones if the resolution changed
+    pixels: Vec<(usize, u8)>,
+}
+
+impl CPU {
+    /// Same as [`CPU::cycle`], but also returns how to take the instruction back
+    pub fn cycle_with_undo(&mut self) -> (Result<(), ExecutionError>, Undo) {
+        let instruction = self
+            .peek_opcode()
+            .and_then(|opcode| Instruction::decode(opcode).ok());
+        let changes = |f: fn(&Instruction) -> bool| instruction.as_ref().is_some_and(f);
+
+        let written = match instruction {
+            Some(Instruction::Bcd { .. }) => Some(3),
+            Some(Instruction::Store { x }) => Some(x + 1),
+            Some(Instruction::SaveRange { x, y }) => Some(x.abs_diff(y) + 1),
+            _ => None,
+        }
+        .map(|len| {
+            let start = (self.i_register as usize).min(self.memory.len());
+            let end = (start + len).min(self.memory.len());
+            (start..end)
+                .map(|address| (address, self.memory[address]))
+                .collect::<Vec<_>>()
+        });
+        let screen = changes(|i| {
+            use Instruction::*;
+            matches!(
+                i,
+                ClearScreen | ScrollRight | ScrollLeft | ScrollDown(_) | ScrollUp(_)
+                    | LowRes | HighRes | SelectPlanes(_) | Draw { .. }
+            )
+        })
+        .then(|| {
+            let graphics = &self.graphics_memory;
+            let pixels: Vec<u8> = graphics.iter().copied().collect();
+            (graphics.hires(), graphics.selected_planes(), pixels)
+        });
+
+        let mut undo = Undo {
+            registers: self.register_memory,
+            i_register: self.i_register,
+            instruction_ptr: self.instruction_ptr,
+            delay_timer: self.delay_timer.read(),
+            sound_timer: self.sound_timer.read(),
+            exited: self.exited,
+            stack: changes(|i| matches!(i, Instruction::Call(_) | Instruction::Return))
+                .then(|| self.stack.clone()),
+            memory: Vec::new(),
+            screen: None,
+            rng: changes(|i| matches!(i, Instruction::Random { .. })).then(|| self.rng.clone()),
+            audio: changes(|i| matches!(i, Instruction::LoadAudio | Instruction::SetPitch { .. }))
+                .then(|| self.audio.clone()),
+            rpl_flags: changes(|i| matches!(i, Instruction::SaveFlags { .. }))
+                .then_some(self.rpl_flags),
+        };
+
+        let result = self.cycle();
+
+        // only keep what did change
+        if let Some(written) = written {
+            undo.memory = written
+                .into_iter()
+                .filter(|&(address, old)| self.memory[address] != old)
+                .collect();
+        }
+        if let Some((hires, selected_planes, old)) = screen {
+            let graphics = &self.graphics_memory;
+            let pixels = old.into_iter().enumerate();
+            let pixels = match graphics.hires() == hires {
+                true => pixels.filter(|&(n, old)| graphics[n] != old).collect(),
+                false => pixels.filter(|&(_, old)| old != 0).collect(),
+            };
+            undo.screen = Some(ScreenUndo {
+                hires,
+                selected_planes,
+                pixels,
+            });
+        }
+        (result, undo)
+    }
+
+    /// Puts back everything the instruction of `undo` changed
+    pub fn undo(&mut self, undo: Undo) {
+        self.register_memory = undo.registers;
+        self.i_register = undo.i_register;
+        self.instruction_ptr = undo.instruction_ptr;
+        self.delay_timer.set_timer(undo.delay_timer);
+        self.sound_timer.set_timer(undo.sound_timer);
+        self.exited = undo.exited;
+        if let Some(stack) = undo.stack {
+            self.stack = stack;
+        }
+        for (address, value) in undo.memory {
+            self.memory[address] = value;
+        }
+        if let Some(screen) = undo.screen {
+            let graphics = &mut self.graphics_memory;
+            if graphics.hires() != screen.hires {
+                graphics.set_hires(screen.hires);
+            }
+            graphics.select_planes(screen.selected_planes);
+            for (n, value) in screen.pixels {
+                graphics[n] = value;
+            }
+        }
+        if let Some(rng) = undo.rng {
+            self.rng = rng;
+        }
+        if let Some(audio) = undo.audio {
+            self.audio = audio;
+        }
+        if let Some(rpl_flags) = undo.rpl_flags {
+            self.rpl_flags = rpl_flags;
+        }
+        self.memory_accesses.clear();
+    }
+}
+
+use super::{ExecutionError, Instruction, CPU};
+use crate::audio::Audio;
+use crate::rng::Rng;
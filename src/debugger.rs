@@ -0,0 +1,869 @@
+//! A command line debugger, fed one line at a time.
+//!
+//! It doesnt read or print anything itself, every command returns the text to show,
+//! so it works the same with a terminal, a socket or a test.
+
+/// Breakpoints, watchpoints, and whether the machine is stopped.
+///
+/// While stopped the machine only moves on the `step` command,
+/// otherwise [`Debugger::run_frame`] runs it, stopping at the breakpoints.
+pub struct Debugger {
+    /// The unconditional ones have no condition
+    breakpoints: BTreeMap<usize, Option<Expr>>,
+    /// Breakpoints without an address, checked before every instruction
+    conditions: Vec<Expr>,
+    watchpoints: Vec<Watchpoint>,
+    paused: bool,
+    /// The address the machine was resumed on, its breakpoint is skipped once
+    resumed_at: Option<usize>,
+    /// Repeated on an empty line, like gdb
+    last_command: String,
+    /// Also show the whole memory, whenever the machine stops
+    dump_memory: bool,
+    /// Written before every instruction that goes through [`Self::step`]
+    trace: Option<Trace>,
+    /// What the instructions that went through [`Self::step`] read/wrote
+    accesses: AccessHistory,
+    /// Everything the instructions that went through [`Self::step`] executed, read and wrote
+    coverage: Coverage,
+    /// The instructions that went through [`Self::step`], newest last
+    history: VecDeque<Executed>,
+    /// Most instructions [`Self::history`] keeps, 0 for none
+    history_limit: usize,
+    /// Names the addresses can be given by, and are shown with
+    symbols: Symbols,
+    /// Counts every instruction that goes through [`Self::step`]
+    profile: Option<Profile>,
+    /// Stops once the stack is back to this many calls, for `next` and `finish`
+    return_depth: Option<usize>,
+}
+
+/// An instruction in the history, to step back over it
+struct Executed {
+    undo: Undo,
+    /// What it read/wrote, for the watchpoints
+    accesses: Vec<MemoryAccess>,
+    /// [`Chip8::cycles`] right after it
+    cycles: u64,
+}
+
+/// Stops the machine right after an instruction touched the memory in `range`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub watch: Watch,
+}
+
+/// The accesses a watchpoint stops on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Watch {
+    Read,
+    Write,
+    /// Both reads and writes
+    Access,
+}
+
+/// How many instructions can be stepped back by default, about 2 minutes at the default speed
+pub const DEFAULT_HISTORY_LIMIT: usize = 1 << 16;
+
+/// `help` prints this
+const HELP: &str = "\
+step [N]            (s)  execute N instructions, 1 by default
+continue            (c)  run till a breakpoint or a fault
+reverse-step [N]    (rs) take back N instructions, 1 by default
+reverse-continue    (rc) go back till a breakpoint, a watchpoint or the oldest instruction kept
+next                (n)  step, running a whole subroutine call as one step
+finish              (fin) run till the current subroutine returns
+pause               (p)  stop the machine
+break [ADDR]        (b)  stop before executing ADDR, lists the breakpoints without ADDR
+break [ADDR] if EXPR     stop only when EXPR holds, before any instruction without ADDR
+                         e.g. `break 0x2A0 if V3 == 0x10 && I > 0x300`, `[ADDR]` reads memory
+delete [ADDR]       (d)  remove the breakpoint at ADDR, or everything
+delete if                remove the breakpoints without an address
+watch ADDR [LEN]         stop after an instruction writes LEN bytes from ADDR, 1 by default
+rwatch ADDR [LEN]        same, for reads by DXYN, FX65...
+awatch ADDR [LEN]        same, for both reads and writes
+watch                    lists the watchpoints
+unwatch [ADDR]           remove the watchpoints starting at ADDR, or all of them
+registers           (r)  V0-VF, I, PC, the stack and the timers
+backtrace           (bt) the calls the machine is in, with their call sites and callees
+x ADDR [LEN]             show LEN bytes of memory from ADDR, 16 by default
+mem [ADDR] [LEN]    (m)  hex view of LEN bytes from ADDR, 128 around PC by default,
+                         marking PC with >, I with *, recent writes with w and reads with r
+poke ADDR BYTE...        write the bytes from ADDR, only while paused
+sprite [ADDR] [LEN]      LEN bytes from ADDR drawn as 8 pixel wide rows, 15 from I by default
+set REG VALUE            change V0-VF, I, PC, DT or ST
+disassemble [N]     (dis) N instructions around PC, 5 by default
+profile                  the hot spots, subroutines and opcodes so far, with --profile
+help                (h)  this
+An empty line repeats the last command. Numbers are decimal, or hex with 0x.
+An ADDR can also be a symbol, e.g. `break draw_player`, see --symbols.";
+
+impl Debugger {
+    /// `paused` starts the machine stopped, before its first instruction
+    pub fn new(paused: bool) -> Self {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            conditions: Vec::new(),
+            watchpoints: Vec::new(),
+            paused,
+            resumed_at: None,
+            last_command: "step".to_string(),
+            dump_memory: false,
+            trace: None,
+            accesses: AccessHistory::new(),
+            coverage: Coverage::new(),
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            symbols: Symbols::new(),
+            profile: None,
+            return_depth: None,
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns where the machine stopped
+    pub fn pause(&mut self, machine: &Chip8) -> String {
+        self.paused = true;
+        self.return_depth = None;
+        self.stopped_at(machine)
+    }
+
+    pub fn resume(&mut self, machine: &Chip8) {
+        self.paused = false;
+        self.resumed_at = Some(machine.program_counter());
+    }
+
+    /// Show [`Chip8::dump`] along with the usual stop message
+    pub fn set_dump_memory(&mut self, dump_memory: bool) {
+        self.dump_memory = dump_memory;
+    }
+
+    /// Traces every instruction executed from now on
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Profiles every instruction executed from now on
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = Some(profile);
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// How many of the last instructions can be stepped back over, 0 turns it off
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Lets the commands take names instead of addresses, and shows them
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Executes a single instruction, ignoring the breakpoints, but tracing it
+    /// and remembering how to take it back
+    pub fn step(&mut self, machine: &mut Chip8) -> Result<(), ExecutionError> {
+        if let Some(trace) = &mut self.trace {
+            if let Err(e) = trace.record(machine, &self.symbols) {
+                tracing::error!("Failed to write the trace, it is stopped: {}", e);
+                self.trace = None;
+            }
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record(machine);
+        }
+        let pc = machine.program_counter();
+        let before = machine.cycles();
+        let result = match self.history_limit {
+            0 => machine.step(),
+            _ => machine.step_with_undo().map(|undo| {
+                if let Some(undo) = undo {
+                    self.remember(before, undo, machine);
+                }
+            }),
+        };
+        self.accesses.record(machine);
+        // a fault or an exited machine didnt execute anything
+        if machine.cycles() != before {
+            self.coverage.record(pc, machine);
+        }
+        // the end of the trace is the interesting part of a crash, dont leave it in the buffer
+        if result.is_err() || machine.exited() {
+            self.flush_trace();
+        }
+        result
+    }
+
+    /// Writes out the buffered end of the trace, before exiting
+    pub fn flush_trace(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            if let Err(e) = trace.flush() {
+                tracing::error!("Failed to write the trace, it is stopped: {}", e);
+                self.trace = None;
+            }
+        }
+    }
+
+    fn remember(&mut self, before: u64, undo: Undo, machine: &Chip8) {
+        // the machine was reset, rewound or loaded in between, the history is of another run
+        if self.history.back().is_some_and(|last| last.cycles != before) {
+            self.history.clear();
+        }
+        self.history.push_back(Executed {
+            undo,
+            accesses: machine.memory_accesses().to_vec(),
+            cycles: machine.cycles(),
+        });
+        if self.history.len() > self.history_limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Takes back the last instruction, returns what it read/wrote, or why it cant
+    fn step_back(&mut self, machine: &mut Chip8) -> Result<Vec<MemoryAccess>, String> {
+        let last = self
+            .history
+            .pop_back()
+            .ok_or("No more instructions to step back over")?;
+        if last.cycles != machine.cycles() {
+            self.history.clear();
+            return Err("The machine was reset or loaded, nothing to step back over".to_string());
+        }
+        machine.undo(last.undo);
+        Ok(last.accesses)
+    }
+
+    /// The recent reads/writes of the memory, for highlighting
+    pub fn accesses(&self) -> &AccessHistory {
+        &self.accesses
+    }
+
+    /// Everything executed, read and written since the start
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    /// Addresses of the breakpoints, conditional or not
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address, None);
+    }
+
+    /// Returns whether there was a breakpoint at the address
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, range: Range<usize>, watch: Watch) {
+        self.watchpoints.push(Watchpoint { range, watch });
+    }
+
+    /// Returns whether there was such a watchpoint
+    pub fn remove_watchpoint(&mut self, range: Range<usize>, watch: Watch) -> bool {
+        let watchpoint = Watchpoint { range, watch };
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    /// Runs a frame like [`Chip8::run_frame`], unless paused.
+    ///
+    /// Stops the machine before any instruction with a breakpoint, after any instruction
+    /// that hit a watchpoint, and on a fault, returning the reason.
+    /// The instruction the machine is resumed on never stops it,
+    /// so that continuing from a breakpoint doesnt stop right away.
+    pub fn run_frame(&mut self, machine: &mut Chip8) -> Option<String> {
+        if self.paused {
+            return None;
+        }
+        for _ in 0..machine.instructions_per_frame() {
+            let resumed_here = self.resumed_at.take() == Some(machine.program_counter());
+            if let Some(stop) = self.execute_one(machine, !resumed_here) {
+                self.paused = true;
+                self.return_depth = None;
+                machine.tick_timers();
+                return Some(stop);
+            }
+        }
+        machine.tick_timers();
+        None
+    }
+
+    /// Executes a single instruction, unless it has a breakpoint,
+    /// returns why the machine has to stop, if it has to
+    fn execute_one(&mut self, machine: &mut Chip8, check_breakpoints: bool) -> Option<String> {
+        let pc = machine.program_counter();
+        if check_breakpoints {
+            if let Some(stop) = self.breakpoint_hit(machine) {
+                return Some(stop);
+            }
+        }
+
+        if let Err(e) = self.step(machine) {
+            return Some(format!("{e}\n{}", self.stopped_at(machine)));
+        }
+        if let Some(stop) = self.watchpoint_hit(machine.memory_accesses(), pc, machine) {
+            return Some(stop);
+        }
+        self.return_depth
+            .filter(|&depth| machine.stack().len() <= depth)
+            .map(|_| format!("Returned, {}", self.stopped_at(machine)))
+    }
+
+    /// Why the machine has to stop before the instruction at the PC, if it has to
+    fn breakpoint_hit(&self, machine: &Chip8) -> Option<String> {
+        let pc = machine.program_counter();
+        if let Some(condition) = self.breakpoints.get(&pc) {
+            match condition {
+                None => return Some(format!("Breakpoint, {}", self.stopped_at(machine))),
+                Some(condition) if condition.holds(machine) => {
+                    return Some(format!(
+                        "Breakpoint if {condition}, {}",
+                        self.stopped_at(machine)
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        self.conditions
+            .iter()
+            .find(|condition| condition.holds(machine))
+            .map(|condition| format!("Breakpoint if {condition}, {}", self.stopped_at(machine)))
+    }
+
+    /// Whether the `accesses` of the instruction at `pc` hit a watchpoint
+    fn watchpoint_hit(&self, accesses: &[MemoryAccess], pc: usize, machine: &Chip8) -> Option<String> {
+        let hit = accesses.iter().find_map(|access| {
+            self.watchpoints
+                .iter()
+                .find(|w| {
+                    let kind_matches = matches!(
+                        (w.watch, access.kind),
+                        (Watch::Access, _)
+                            | (Watch::Read, AccessKind::Read)
+                            | (Watch::Write, AccessKind::Write)
+                    );
+                    kind_matches
+                        && w.range.start < access.range.end
+                        && access.range.start < w.range.end
+                })
+                .map(|_| access)
+        });
+        hit.map(|access| {
+            let kind = match access.kind {
+                AccessKind::Read => "read",
+                AccessKind::Write => "write",
+            };
+            format!(
+                "Watchpoint, {kind} of 0x{:04X}..0x{:04X} by 0x{pc:04X}, {}",
+                access.range.start,
+                access.range.end,
+                self.stopped_at(machine)
+            )
+        })
+    }
+
+    /// Executes a command, returns what it has to say
+    pub fn command(&mut self, line: &str, machine: &mut Chip8) -> String {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        self.execute(command, &args, machine)
+            .unwrap_or_else(|e| format!("{e}, see `help`"))
+    }
+
+    fn execute(
+        &mut self,
+        command: &str,
+        args: &[&str],
+        machine: &mut Chip8,
+    ) -> Result<String, String> {
+        let arg = |index: usize| args.get(index).map(|arg| parse_number(arg)).transpose();
+        let symbols = &self.symbols;
+        let address_arg = |index: usize| {
+            args.get(index)
+                .map(|arg| parse_address(arg, symbols))
+                .transpose()
+        };
+        Ok(match command {
+            "s" | "step" => {
+                let count = arg(0)?.unwrap_or(1);
+                self.paused = true;
+                self.return_depth = None;
+                for n in 0..count {
+                    // the breakpoint of the instruction being stepped from doesnt stop it
+                    if let Some(stop) = self.execute_one(machine, n > 0) {
+                        return Ok(stop);
+                    }
+                }
+                self.stopped_at(machine)
+            }
+            "rs" | "reverse-step" => {
+                let count = arg(0)?.unwrap_or(1);
+                self.paused = true;
+                for _ in 0..count {
+                    if let Err(e) = self.step_back(machine) {
+                        return Ok(format!("{e}, {}", self.stopped_at(machine)));
+                    }
+                }
+                self.stopped_at(machine)
+            }
+            "rc" | "reverse-continue" => {
+                self.paused = true;
+                self.reverse_continue(machine).unwrap_or_else(|e| e)
+            }
+            "n" | "next" | "step-over" => {
+                let pc = machine.program_counter();
+                let calls = matches!(
+                    opcode_at(machine, pc).map(Instruction::decode),
+                    Some(Ok(Instruction::Call(_)))
+                );
+                if !calls {
+                    return self.execute("step", &[], machine);
+                }
+                // runs till the call returns, which can take more than a frame
+                self.return_depth = Some(machine.stack().len());
+                self.resume(machine);
+                format!("Running the call at {}", self.describe(pc))
+            }
+            "fin" | "finish" | "step-out" => {
+                let depth = machine.stack().len();
+                let Some(frame) = call_stack(machine).pop() else {
+                    return Err("Not in a subroutine".to_string());
+                };
+                self.return_depth = Some(depth - 1);
+                self.resume(machine);
+                format!("Running till the return to {}", self.describe(frame.call_site + 2))
+            }
+            "bt" | "backtrace" => backtrace(machine, symbols),
+            "profile" => match &self.profile {
+                Some(profile) => profile.report(symbols),
+                None => return Err("Not profiling, start with --profile".to_string()),
+            },
+            "c" | "continue" => {
+                self.resume(machine);
+                "Continuing".to_string()
+            }
+            "p" | "pause" => self.pause(machine),
+            "b" | "break" => {
+                let (address, condition) = match args.iter().position(|&arg| arg == "if") {
+                    Some(index) => (
+                        &args[..index],
+                        Some(Expr::parse(&args[index + 1..].join(" "))?),
+                    ),
+                    None => (args, None),
+                };
+                let address = address
+                    .first()
+                    .map(|address| parse_address(address, symbols))
+                    .transpose()?;
+                match (address, condition) {
+                    (Some(address), condition) => {
+                        let at = self.describe(address);
+                        let message = match &condition {
+                            Some(condition) => format!("Breakpoint at {at} if {condition}"),
+                            None => format!("Breakpoint at {at}"),
+                        };
+                        self.breakpoints.insert(address, condition);
+                        message
+                    }
+                    (None, Some(condition)) => {
+                        let message = format!("Breaking anywhere if {condition}");
+                        self.conditions.push(condition);
+                        message
+                    }
+                    (None, None) => self.list_breakpoints(),
+                }
+            }
+            "d" | "delete" => match args.first() {
+                Some(&"if") => {
+                    self.conditions.clear();
+                    "Deleted the breakpoints without an address".to_string()
+                }
+                Some(address) => {
+                    let address = parse_address(address, symbols)?;
+                    let at = self.describe(address);
+                    match self.breakpoints.remove(&address) {
+                        Some(_) => format!("Deleted the breakpoint at {at}"),
+                        None => format!("No breakpoint at {at}"),
+                    }
+                }
+                None => {
+                    self.breakpoints.clear();
+                    self.conditions.clear();
+                    self.watchpoints.clear();
+                    "Deleted all the breakpoints and watchpoints".to_string()
+                }
+            },
+            "watch" | "rwatch" | "awatch" => {
+                let Some(address) = address_arg(0)? else {
+                    return Ok(self.list_watchpoints());
+                };
+                let len = arg(1)?.unwrap_or(1).max(1);
+                let watch = match command {
+                    "rwatch" => Watch::Read,
+                    "awatch" => Watch::Access,
+                    _ => Watch::Write,
+                };
+                let Some(end) = address.checked_add(len) else {
+                    return Err(format!("{len} bytes from 0x{address:04X} go past the last address"));
+                };
+                self.add_watchpoint(address..end, watch);
+                format!(
+                    "Watching {}",
+                    describe_watchpoint(self.watchpoints.last().unwrap())
+                )
+            }
+            "unwatch" => match address_arg(0)? {
+                Some(address) => {
+                    self.watchpoints.retain(|w| w.range.start != address);
+                    format!("Stopped watching 0x{address:04X}")
+                }
+                None => {
+                    self.watchpoints.clear();
+                    "Deleted all the watchpoints".to_string()
+                }
+            },
+            "r" | "registers" => registers(machine, symbols),
+            "x" => {
+                let address = address_arg(0)?.ok_or("Expected an address")?;
+                let len = arg(1)?.unwrap_or(16);
+                examine(machine, address, len)
+            }
+            "m" | "mem" => {
+                let pc = machine.program_counter();
+                let line = memview::BYTES_PER_LINE;
+                let around_pc = (pc / line).saturating_sub(2) * line;
+                let address = address_arg(0)?.unwrap_or(around_pc);
+                let len = arg(1)?.unwrap_or(8 * line);
+                self.memory_view(machine, address, len)
+            }
+            "poke" => {
+                if !self.paused {
+                    return Err("Pause the machine before poking its memory".to_string());
+                }
+                let address = address_arg(0)?.ok_or("Expected an address")?;
+                let bytes = args[1..]
+                    .iter()
+                    .map(|arg| {
+                        let value = parse_number(arg)?;
+                        u8::try_from(value).map_err(|_| format!("{value} doesnt fit in a byte"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if bytes.is_empty() {
+                    return Err("Expected the bytes to write".to_string());
+                }
+                let end = address.checked_add(bytes.len());
+                let Some(end) = end.filter(|&end| end <= machine.memory().len()) else {
+                    return Err(format!("{} bytes at 0x{address:04X} are out of the memory", bytes.len()));
+                };
+                for (n, byte) in bytes.into_iter().enumerate() {
+                    machine.set_memory(address + n, byte);
+                }
+                self.memory_view(machine, address, end - address)
+            }
+            "sprite" => {
+                let address = address_arg(0)?.unwrap_or(machine.i_register() as usize);
+                let len = arg(1)?.unwrap_or(15);
+                if address >= machine.memory().len() {
+                    return Err(format!("0x{address:04X} is out of the memory"));
+                }
+                memview::sprites(machine.memory(), address..address.saturating_add(len))
+            }
+            "set" => {
+                let (Some(register), Some(value)) = (args.first(), arg(1)?) else {
+                    return Err("Expected a register and a value".to_string());
+                };
+                set(machine, register, value)?;
+                registers(machine, &self.symbols)
+            }
+            "dis" | "disassemble" => disassemble(machine, arg(0)?.unwrap_or(5), symbols),
+            "h" | "help" => HELP.to_string(),
+            _ => return Err(format!("Unknown command `{command}`")),
+        })
+    }
+
+    /// Steps back till a breakpoint or a watchpoint would have stopped the machine,
+    /// i.e. till right before an instruction with a breakpoint, or one that hit a watchpoint.
+    ///
+    /// Returns where it stopped, as an error if it ran out of the history instead.
+    pub fn reverse_continue(&mut self, machine: &mut Chip8) -> Result<String, String> {
+        // the breakpoint of the instruction being reversed from doesnt stop it
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(stop) = self.breakpoint_hit(machine) {
+                    return Ok(stop);
+                }
+            }
+            first = false;
+            let accesses = self
+                .step_back(machine)
+                .map_err(|e| format!("{e}, {}", self.stopped_at(machine)))?;
+            let pc = machine.program_counter();
+            if let Some(stop) = self.watchpoint_hit(&accesses, pc, machine) {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Takes back the last instruction, for gdb's reverse stepping
+    pub fn reverse_step(&mut self, machine: &mut Chip8) -> Result<(), String> {
+        self.step_back(machine).map(|_| ())
+    }
+
+    fn list_breakpoints(&self) -> String {
+        let at_address = self
+            .breakpoints
+            .iter()
+            .map(|(&address, condition)| match condition {
+                Some(condition) => format!("{} if {condition}", self.describe(address)),
+                None => self.describe(address),
+            });
+        let anywhere = self
+            .conditions
+            .iter()
+            .map(|condition| format!("anywhere if {condition}"));
+        let list: Vec<_> = at_address.chain(anywhere).collect();
+        match list.is_empty() {
+            true => "No breakpoints".to_string(),
+            false => list.join("\n"),
+        }
+    }
+
+    fn list_watchpoints(&self) -> String {
+        match self.watchpoints.is_empty() {
+            true => "No watchpoints".to_string(),
+            false => self
+                .watchpoints
+                .iter()
+                .map(describe_watchpoint)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// [`memview::hex`] with the PC, I and the recent accesses marked
+    fn memory_view(&self, machine: &Chip8, address: usize, len: usize) -> String {
+        if address >= machine.memory().len() {
+            return format!("0x{address:04X} is out of the memory");
+        }
+        let pc = machine.program_counter();
+        let i = machine.i_register() as usize;
+        let mark = |a: usize| match self.accesses.recent(a, machine.cycles()) {
+            _ if a == pc || a == pc + 1 => '>',
+            _ if a == i => '*',
+            Some(AccessKind::Write) => 'w',
+            Some(AccessKind::Read) => 'r',
+            None => ' ',
+        };
+        memview::hex(machine.memory(), address..address.saturating_add(len), &mark)
+    }
+
+    /// `0x022C`, along with its symbol if it has one, e.g. `0x022C (draw_player+0x2)`
+    fn describe(&self, address: usize) -> String {
+        describe_address(address, &self.symbols)
+    }
+
+    fn stopped_at(&self, machine: &Chip8) -> String {
+        let pc = machine.program_counter();
+        let mut message = format!(
+            "Stopped at {}: {}",
+            self.describe(pc),
+            mnemonic(machine, pc, &self.symbols)
+        );
+        if self.dump_memory {
+            message = format!("{message}\n{}", machine.dump());
+        }
+        message
+    }
+}
+
+fn describe_watchpoint(watchpoint: &Watchpoint) -> String {
+    let watch = match watchpoint.watch {
+        Watch::Read => "reads",
+        Watch::Write => "writes",
+        Watch::Access => "reads and writes",
+    };
+    let range = &watchpoint.range;
+    format!("{watch} of 0x{:04X}..0x{:04X}", range.start, range.end)
+}
+
+/// `0x022C`, along with its symbol if it has one
+fn describe_address(address: usize, symbols: &Symbols) -> String {
+    match symbols.locate(address) {
+        Some(symbol) => format!("0x{address:04X} ({symbol})"),
+        None => format!("0x{address:04X}"),
+    }
+}
+
+/// The address of a symbol, or a number
+fn parse_address(text: &str, symbols: &Symbols) -> Result<usize, String> {
+    match symbols.address(text) {
+        Some(address) => Ok(address),
+        None => parse_number(text).map_err(|_| format!("`{text}` is not an address or a symbol")),
+    }
+}
+
+/// Decimal, or hex with a `0x`
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("`{text}` is not a number"))
+}
+
+fn opcode_at(machine: &Chip8, address: usize) -> Option<u16> {
+    let memory = machine.memory();
+    (address + 1 < memory.len()).then(|| u16::from_be_bytes([memory[address], memory[address + 1]]))
+}
+
+fn registers(machine: &Chip8, symbols: &Symbols) -> String {
+    let v = machine.registers();
+    let row = |range: std::ops::Range<usize>| {
+        range
+            .map(|x| format!("V{x:X} {:02X}", v[x]))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let stack = machine
+        .stack()
+        .iter()
+        .map(|&address| describe_address(address, symbols))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "PC 0x{:04X}  I 0x{:04X}  SP {}  DT {}  ST {}\n{}\n{}\nStack: [{}]",
+        machine.program_counter(),
+        machine.i_register(),
+        machine.stack().len(),
+        machine.delay_timer(),
+        machine.sound_timer(),
+        row(0..8),
+        row(8..16),
+        stack,
+    )
+}
+
+fn examine(machine: &Chip8, address: usize, len: usize) -> String {
+    let memory = machine.memory();
+    let end = address.saturating_add(len).min(memory.len());
+    if address >= end {
+        return format!("0x{address:04X} is out of the memory");
+    }
+    (address..end)
+        .step_by(16)
+        .map(|line| {
+            let bytes = (line..(line + 16).min(end)).map(|a| format!("{:02X}", memory[a]));
+            format!("0x{line:04X}: {}", bytes.collect::<Vec<_>>().join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn set(machine: &mut Chip8, register: &str, value: usize) -> Result<(), String> {
+    let byte = || u8::try_from(value).map_err(|_| format!("{value} doesnt fit in a byte"));
+    match register.to_ascii_uppercase().as_str() {
+        "I" => machine
+            .set_i_register(u16::try_from(value).map_err(|_| format!("{value} doesnt fit in I"))?),
+        "PC" => {
+            if value >= machine.memory().len() {
+                return Err(format!("0x{value:04X} is out of the memory"));
+            }
+            machine.set_program_counter(value);
+        }
+        "DT" => machine.set_delay_timer(byte()?),
+        "ST" => machine.set_sound_timer(byte()?),
+        name => {
+            let index = name
+                .strip_prefix('V')
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .filter(|&x| x < 16)
+                .ok_or(format!("Unknown register `{register}`"))?;
+            machine.set_register(index, byte()?);
+        }
+    }
+    Ok(())
+}
+
+/// `count` instructions, about half of them before PC, with a line for each symbol
+fn disassemble(machine: &Chip8, count: usize, symbols: &Symbols) -> String {
+    let pc = machine.program_counter();
+    let start = pc.saturating_sub(count / 2 * 2);
+    (0..count)
+        .map(|n| start + n * 2)
+        .map_while(|address| opcode_at(machine, address).map(|opcode| (address, opcode)))
+        .map(|(address, opcode)| {
+            let marker = if address == pc { "=>" } else { "  " };
+            let line = format!(
+                "{marker} 0x{address:04X}: {opcode:04X}  {}",
+                mnemonic(machine, address, symbols)
+            );
+            match symbols.name(address) {
+                Some(name) => format!("{name}:\n{line}"),
+                None => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The classic assembly of the instruction at `address`, with the symbols as its labels
+fn mnemonic(machine: &Chip8, address: usize, symbols: &Symbols) -> String {
+    let Some(opcode) = opcode_at(machine, address) else {
+        return "??".to_string();
+    };
+    match Instruction::decode(opcode) {
+        Ok(instruction) => {
+            let long = opcode_at(machine, address + 2);
+            let label = |target: u16| symbols.name(target as usize).map(str::to_string);
+            instruction.format(Syntax::Classic, long, &label)
+        }
+        Err(_) => format!("DW 0x{opcode:04X}"),
+    }
+}
+
+pub use callstack::{backtrace, call_stack, Frame};
+pub use expr::Expr;
+
+/// The calls the machine is in, read from its stack
+mod callstack;
+/// Conditions of the breakpoints
+mod expr;
+
+use crate::coverage::Coverage;
+use crate::cpu::{ExecutionError, Instruction, Syntax, Undo};
+use crate::memory::{AccessKind, MemoryAccess};
+use crate::memview::{self, AccessHistory};
+use crate::profile::Profile;
+use crate::symbols::Symbols;
+use crate::trace::Trace;
+use crate::Chip8;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
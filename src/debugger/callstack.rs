@@ -0,0 +1,60 @@
+/// A subroutine call that hasnt returned yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// Address of the `2NNN` that made the call
+    pub call_site: usize,
+    /// Where the call went, `None` if the call site doesnt hold a `2NNN` anymore
+    pub callee: Option<usize>,
+}
+
+/// The calls the machine is in, outermost first.
+///
+/// The stack only has the return addresses, the call sites are right before them
+/// and the callees are read back from the `2NNN` there, which self modifying code can fool.
+pub fn call_stack(machine: &Chip8) -> Vec<Frame> {
+    let memory = machine.memory();
+    machine
+        .stack()
+        .iter()
+        .map(|&return_address| {
+            let call_site = return_address.wrapping_sub(2);
+            let callee = (call_site + 1 < memory.len())
+                .then(|| u16::from_be_bytes([memory[call_site], memory[call_site + 1]]))
+                .and_then(|opcode| match Instruction::decode(opcode) {
+                    Ok(Instruction::Call(target)) => Some(target as usize),
+                    _ => None,
+                });
+            Frame { call_site, callee }
+        })
+        .collect()
+}
+
+/// One line per frame, innermost first like gdb's `backtrace`, starting with where the PC is
+pub fn backtrace(machine: &Chip8, symbols: &Symbols) -> String {
+    let frames = call_stack(machine);
+    let mut lines = vec![format!(
+        "#0  {}",
+        super::describe_address(machine.program_counter(), symbols)
+    )];
+    for (n, frame) in frames.iter().rev().enumerate() {
+        let callee = match frame.callee {
+            Some(callee) => super::describe_address(callee, symbols),
+            None => "?".to_string(),
+        };
+        lines.push(format!(
+            "#{:<2} {} calls {callee}",
+            n + 1,
+            super::describe_address(frame.call_site, symbols)
+        ));
+    }
+    lines.push(format!(
+        "{} of {} frames used",
+        frames.len(),
+        machine.quirks().stack_depth
+    ));
+    lines.join("\n")
+}
+
+use crate::cpu::Instruction;
+use crate::symbols::Symbols;
+use crate::Chip8;
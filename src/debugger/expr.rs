@@ -0,0 +1,361 @@
+/// A condition over the machine, as in `V3 == 0x10 && I > 0x300`.
+///
+/// Operands are numbers (decimal, or hex with `0x`), the registers `V0`-`VF`, `I`, `PC`,
+/// `SP`, `DT` and `ST`, and bytes of memory as `[ADDR]`, where ADDR is an expression itself.
+/// Operators, loosest first: `||`, `&&`, comparisons, `|`, `^`, `&`, `+ -`, and the unary `!`.
+/// Anything that isnt 0 is true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Binary(Box<Node>, Op, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+/// Most `(`, `[` and `!` inside one another, so that the parser cant run out of stack
+const MAX_NESTING: usize = 64;
+
+/// Operators of each precedence level, loosest first
+const LEVELS: &[&[(&str, Op)]] = &[
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ],
+    &[("|", Op::BitOr)],
+    &[("^", Op::BitXor)],
+    &[("&", Op::BitAnd)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+];
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            rest: source,
+            nesting: 0,
+        };
+        let root = parser.level(0)?;
+        parser.skip_whitespace();
+        if !parser.rest.is_empty() {
+            return Err(format!("Unexpected `{}` in the condition", parser.rest));
+        }
+        Ok(Expr {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, machine: &Chip8) -> i64 {
+        self.root.evaluate(machine)
+    }
+
+    pub fn holds(&self, machine: &Chip8) -> bool {
+        self.evaluate(machine) != 0
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Node {
+    fn evaluate(&self, machine: &Chip8) -> i64 {
+        match self {
+            Node::Number(number) => *number,
+            Node::Register(register) => match register {
+                Register::V(x) => machine.registers()[*x] as i64,
+                Register::I => machine.i_register() as i64,
+                Register::Pc => machine.program_counter() as i64,
+                Register::Sp => machine.stack().len() as i64,
+                Register::Dt => machine.delay_timer() as i64,
+                Register::St => machine.sound_timer() as i64,
+            },
+            // outside of the memory reads as 0
+            Node::Memory(address) => usize::try_from(address.evaluate(machine))
+                .ok()
+                .filter(|&address| address < machine.memory().len())
+                .map_or(0, |address| machine.memory()[address] as i64),
+            Node::Not(node) => (node.evaluate(machine) == 0) as i64,
+            Node::Binary(left, op, right) => {
+                let left = left.evaluate(machine);
+                // the right side of && and || is only looked at when needed
+                match op {
+                    Op::Or if left != 0 => return 1,
+                    Op::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.evaluate(machine);
+                match op {
+                    Op::Or | Op::And => (right != 0) as i64,
+                    Op::Eq => (left == right) as i64,
+                    Op::Ne => (left != right) as i64,
+                    Op::Lt => (left < right) as i64,
+                    Op::Le => (left <= right) as i64,
+                    Op::Gt => (left > right) as i64,
+                    Op::Ge => (left >= right) as i64,
+                    Op::BitOr => left | right,
+                    Op::BitXor => left ^ right,
+                    Op::BitAnd => left & right,
+                    Op::Add => left.wrapping_add(right),
+                    Op::Sub => left.wrapping_sub(right),
+                }
+            }
+        }
+    }
+}
+
+/// Recursive descent, one level of [`LEVELS`] at a time
+struct Parser<'a> {
+    rest: &'a str,
+    /// How deep in `(`, `[` and `!` the parser is
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    /// Takes `token` if it is next, `&` doesnt take the start of a `&&`
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let Some(rest) = self.rest.strip_prefix(token) else {
+            return false;
+        };
+        let doubled = matches!(token, "&" | "|") && rest.starts_with(token);
+        if doubled {
+            return false;
+        }
+        self.rest = rest;
+        true
+    }
+
+    fn level(&mut self, level: usize) -> Result<Node, String> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.level(level + 1)?;
+        'operators: loop {
+            for &(token, op) in operators.iter() {
+                if self.eat(token) {
+                    let right = self.level(level + 1)?;
+                    left = Node::Binary(Box::new(left), op, Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.nesting == MAX_NESTING {
+            return Err("The condition is nested too deep".to_string());
+        }
+        self.nesting += 1;
+        let node = self.nested();
+        self.nesting -= 1;
+        node
+    }
+
+    /// A `!`, `(...)` or `[...]`, or else an operand
+    fn nested(&mut self) -> Result<Node, String> {
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let node = self.level(0)?;
+            return match self.eat(")") {
+                true => Ok(node),
+                false => Err("Missing `)` in the condition".to_string()),
+            };
+        }
+        if self.eat("[") {
+            let address = self.level(0)?;
+            return match self.eat("]") {
+                true => Ok(Node::Memory(Box::new(address))),
+                false => Err("Missing `]` in the condition".to_string()),
+            };
+        }
+        self.operand()
+    }
+
+    fn operand(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        let len = self
+            .rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(len);
+        if word.is_empty() {
+            return Err(match self.rest {
+                "" => "The condition ends too soon".to_string(),
+                rest => format!("Unexpected `{rest}` in the condition"),
+            });
+        }
+        self.rest = rest;
+
+        let register = match word.to_ascii_uppercase().as_str() {
+            "I" => Some(Register::I),
+            "PC" => Some(Register::Pc),
+            "SP" => Some(Register::Sp),
+            "DT" => Some(Register::Dt),
+            "ST" => Some(Register::St),
+            upper => upper
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+                .map(Register::V),
+        };
+        if let Some(register) = register {
+            return Ok(Node::Register(register));
+        }
+        let number = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => word.parse(),
+        };
+        number
+            .map(Node::Number)
+            .map_err(|_| format!("`{word}` is neither a number nor a register"))
+    }
+}
+
+use crate::Chip8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, machine: &Chip8) -> i64 {
+        Expr::parse(source).unwrap().evaluate(machine)
+    }
+
+    fn machine() -> Chip8 {
+        let mut machine = Chip8::new(&[0x12, 0x34, 0x56]).unwrap();
+        machine.set_register(3, 0x10);
+        machine.set_register(0xF, 1);
+        machine.set_i_register(0x201);
+        machine.set_delay_timer(60);
+        machine
+    }
+
+    #[test]
+    fn precedence() {
+        let machine = machine();
+        for (source, value) in [
+            ("1 + 2 == 3", 1),
+            ("1 | 2 == 2", 0),
+            ("1 | 2 & 0", 1),
+            ("6 ^ 3 & 1", 7),
+            ("0 || 1 && 0", 0),
+            ("1 || 0 && 0", 1),
+            ("1 < 2 == 1", 1),
+            ("!0 + 1", 2),
+            ("10 - 3 - 2", 5),
+            ("0 - 1", -1),
+        ] {
+            assert_eq!(evaluate(source, &machine), value, "{source}");
+        }
+    }
+
+    #[test]
+    fn parentheses() {
+        let machine = machine();
+        assert_eq!(evaluate("(1 | 2) == 3", &machine), 1);
+        assert_eq!(evaluate("10 - (3 - 2)", &machine), 9);
+        assert_eq!(evaluate("!(1 && 0)", &machine), 1);
+        assert_eq!(evaluate("((((7))))", &machine), 7);
+    }
+
+    #[test]
+    fn registers() {
+        let machine = machine();
+        assert!(Expr::parse("V3 == 0x10 && I > 0x200").unwrap().holds(&machine));
+        assert_eq!(evaluate("v3 + vf", &machine), 0x11);
+        assert_eq!(evaluate("vF", &machine), 1);
+        assert_eq!(evaluate("pc", &machine), 0x200);
+        assert_eq!(evaluate("SP", &machine), 0);
+        assert_eq!(evaluate("DT - ST", &machine), 60);
+    }
+
+    #[test]
+    fn memory() {
+        let machine = machine();
+        assert_eq!(evaluate("[0x200]", &machine), 0x12);
+        assert_eq!(evaluate("[I] + [I + 1]", &machine), 0x34 + 0x56);
+        assert_eq!(evaluate("[[0x201] + 0x1CC]", &machine), 0x12);
+        // outside of the memory reads as 0
+        assert_eq!(evaluate("[0x10000]", &machine), 0);
+        assert_eq!(evaluate("[0 - 1]", &machine), 0);
+    }
+
+    #[test]
+    fn malformed_conditions_are_errors() {
+        for source in [
+            "", "1 +", "(1", "(1 + 2", "[I", "V3 ==", "1 2", "V10", "0xZZ", "1 === 2", "&& 1",
+            "!", "()", "[]", "V3 = 1", "1 + @",
+        ] {
+            assert!(Expr::parse(source).is_err(), "{source}");
+        }
+        assert_eq!(Expr::parse("(1").unwrap_err(), "Missing `)` in the condition");
+        assert_eq!(
+            Expr::parse(&"(".repeat(100_000)).unwrap_err(),
+            "The condition is nested too deep"
+        );
+        assert_eq!(
+            Expr::parse(&"!".repeat(100_000)).unwrap_err(),
+            "The condition is nested too deep"
+        );
+        assert_eq!(Expr::parse("1 +").unwrap_err(), "The condition ends too soon");
+        assert_eq!(Expr::parse("1 2").unwrap_err(), "Unexpected `2` in the condition");
+        assert_eq!(
+            Expr::parse("V10").unwrap_err(),
+            "`V10` is neither a number nor a register"
+        );
+    }
+
+    #[test]
+    fn shows_the_source() {
+        assert_eq!(Expr::parse("  V3 == 1 ").unwrap().to_string(), "V3 == 1");
+    }
+}
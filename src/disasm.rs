@@ -0,0 +1,176 @@
+/// Where the roms are loaded in the memory
+pub const ORIGIN: usize = 0x200;
+
+/// Turns a rom back into assembly, one line per instruction or per few bytes of data.
+///
+/// With `follow_code`, only what [`Analysis`] can reach from the start of the rom
+/// is taken as code, the rest is data.
+/// Otherwise every 2 bytes that decode are taken as an instruction.
+/// The targets of jumps and calls get labels, and so does any data that is loaded into I.
+/// The names in `symbols` win over those made up labels.
+pub fn disassemble(rom: &[u8], syntax: Syntax, follow_code: bool, symbols: &Symbols) -> String {
+    let starts = match follow_code {
+        true => Analysis::new(rom).instructions.into_keys().collect(),
+        false => (0..rom.len() / 2)
+            .map(|n| ORIGIN + n * 2)
+            .filter(|&address| instruction_at(rom, address).is_some())
+            .collect(),
+    };
+    listing(rom, syntax, &starts, symbols, &|_, _| String::new())
+}
+
+/// The listing of [`disassemble`], with the instructions at `starts` and data everywhere else.
+/// `note` gets the address and length of every line, and returns what to add at the end of it
+pub(crate) fn listing(
+    rom: &[u8],
+    syntax: Syntax,
+    starts: &BTreeSet<usize>,
+    symbols: &Symbols,
+    note: &dyn Fn(usize, usize) -> String,
+) -> String {
+    let labels = find_labels(rom, starts, syntax, symbols);
+    let label = |address: u16| labels.get(&(address as usize)).cloned();
+    let comment = match syntax {
+        Syntax::Octo => '#',
+        Syntax::Classic => ';',
+    };
+    let line = |out: &mut String, text: String, address: usize, bytes: &[u8]| {
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        let mut line = format!("    {text:<31} {comment} 0x{address:04X}  {hex}");
+        let note = note(address, bytes.len());
+        if !note.is_empty() {
+            // past the 8 bytes of the longest data line
+            line = format!("{line:<63}{note}");
+        }
+        out.push_str(&line);
+        out.push('\n');
+    };
+
+    let mut out = String::new();
+    let mut address = ORIGIN;
+    let end = ORIGIN + rom.len();
+    while address < end {
+        if let Some(name) = labels.get(&address) {
+            out.push_str(&match syntax {
+                Syntax::Octo => format!(": {name}\n"),
+                Syntax::Classic => format!("{name}:\n"),
+            });
+        }
+        let offset = address - ORIGIN;
+        // a start that doesnt decode, e.g. code written at runtime, is listed as data
+        let instruction = starts
+            .contains(&address)
+            .then(|| instruction_at(rom, address))
+            .flatten();
+        if let Some(instruction) = instruction {
+            let len = instruction.size().min(end - address);
+            let long = opcode_at(rom, address + 2);
+            let text = instruction.format(syntax, long, &label);
+            line(&mut out, text, address, &rom[offset..offset + len]);
+            address += len;
+        } else {
+            // data upto the next instruction or label, 8 bytes at most
+            let len = (address..end)
+                .take(8)
+                .enumerate()
+                .find(|&(n, a)| n > 0 && (starts.contains(&a) || labels.contains_key(&a)))
+                .map_or((end - address).min(8), |(n, _)| n);
+            let bytes = &rom[offset..offset + len];
+            let text = match syntax {
+                Syntax::Octo => bytes
+                    .iter()
+                    .map(|byte| format!("0x{byte:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Syntax::Classic => format!(
+                    "DB {}",
+                    bytes
+                        .iter()
+                        .map(|byte| format!("0x{byte:02X}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            line(&mut out, text, address, bytes);
+            address += len;
+        }
+    }
+    out
+}
+
+/// Names of the addresses that are jumped to, called, loaded into I, or in the symbols
+fn find_labels(
+    rom: &[u8],
+    starts: &BTreeSet<usize>,
+    syntax: Syntax,
+    symbols: &Symbols,
+) -> BTreeMap<usize, String> {
+    let in_rom = |address: usize| (ORIGIN..ORIGIN + rom.len()).contains(&address);
+    // a label can only go at the start of a line
+    let placeable = |address: usize| {
+        let inside = starts.range(..address).next_back().is_some_and(|&start| {
+            let len = instruction_at(rom, start).map_or(2, |i| i.size());
+            address < start + len
+        });
+        in_rom(address) && !inside
+    };
+    let mut labels = BTreeMap::new();
+    if syntax == Syntax::Octo {
+        // Octo starts running the program at `main`
+        labels.insert(ORIGIN, "main".to_string());
+    }
+    for &address in starts {
+        let instruction = instruction_at(rom, address);
+        let (target, prefix) = match instruction {
+            Some(Instruction::Call(target)) => (target as usize, "sub"),
+            Some(Instruction::Jump(target) | Instruction::JumpOffset(target)) => {
+                (target as usize, "label")
+            }
+            Some(Instruction::LoadI(target)) => (target as usize, "data"),
+            Some(Instruction::LoadILong) => match opcode_at(rom, address + 2) {
+                Some(target) => (target as usize, "data"),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if !placeable(target) {
+            continue;
+        }
+        // calls win over jumps, and both over data
+        let rank = |name: &str| {
+            ["data", "label", "sub"]
+                .iter()
+                .position(|p| name.starts_with(p))
+        };
+        let name = format!("{prefix}_{target:03X}");
+        match labels.get(&target) {
+            Some(existing) if existing == "main" || rank(existing) >= rank(&name) => {}
+            _ => {
+                labels.insert(target, name);
+            }
+        }
+    }
+    for (address, name) in symbols.iter() {
+        if placeable(address) && symbols.name(address) == Some(name) {
+            labels.insert(address, name.to_string());
+        }
+    }
+    labels
+}
+
+/// The 2 bytes at `address`, if they are both in the rom
+pub(crate) fn opcode_at(rom: &[u8], address: usize) -> Option<u16> {
+    let offset = address.checked_sub(ORIGIN)?;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// The instruction at `address`, if it decodes
+pub(crate) fn instruction_at(rom: &[u8], address: usize) -> Option<Instruction> {
+    opcode_at(rom, address).and_then(|opcode| Instruction::decode(opcode).ok())
+}
+
+use crate::analysis::Analysis;
+use crate::cpu::{Instruction, Syntax};
+use crate::symbols::Symbols;
+use std::collections::{BTreeMap, BTreeSet};
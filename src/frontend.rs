@@ -0,0 +1,14 @@
+//! Everything that talks to the OS: the window, the speakers and the keyboard.
+//! None of this is part of the library.
+
+pub mod gdb;
+pub mod input;
+pub mod overlay;
+pub mod save_slots;
+pub mod sound;
+pub mod window;
+
+/// Everything happens once per frame, at 60Hz
+pub const FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 60);
+
+use std::time::Duration;
@@ -0,0 +1,555 @@
+//! A stub for the GDB remote serial protocol, so that gdb (or any editor that speaks it)
+//! can debug the rom running in the window.
+//!
+//! The socket never blocks, it is polled once per frame, and shares the breakpoints
+//! and the stopping/continuing with the command line debugger.
+//! Watchpoints (`Z2`-`Z4`) go to the debugger's watchpoints too.
+//! The `}` escapes of the packets are undone on the way in, and done on the way out.
+//!
+//! The registers, in the order of `g`/`G` and their numbers for `p`/`P`:
+//! V0..VF (8 bit), I (16 bit), PC (16 bit), SP, DT and ST (8 bit), all little endian.
+//! The same is described in the `target.xml`, that gdb asks for.
+
+/// Number of registers, see the module docs for the order
+const REGISTERS: usize = 21;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Signals of the stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct GdbStub {
+    listener: TcpListener,
+    /// Only one gdb at a time
+    client: Option<TcpStream>,
+    /// Bytes read, that dont make a complete packet yet
+    received: Vec<u8>,
+    /// gdb continued the machine, and waits for it to stop
+    running: bool,
+}
+
+impl GdbStub {
+    /// Listens on localhost
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        tracing::info!("Waiting for gdb on 127.0.0.1:{}", port);
+        Ok(GdbStub {
+            listener,
+            client: None,
+            received: Vec::new(),
+            running: false,
+        })
+    }
+
+    /// Accepts a new gdb, handles whatever the connected one sent, never blocks
+    pub fn poll(&mut self, debugger: &mut Debugger, machine: &mut Chip8) {
+        if self.client.is_none() {
+            self.accept(debugger, machine);
+        }
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+
+        let mut buffer = [0; 4096];
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => return self.disconnect("gdb closed the connection"),
+                Ok(read) => self.received.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return self.disconnect(&e.to_string()),
+            }
+        }
+
+        while let Some(packet) = self.next_packet() {
+            let reply = match packet {
+                Packet::Interrupt => {
+                    debugger.pause(machine);
+                    self.running = false;
+                    Some(stop_reply(SIGINT))
+                }
+                Packet::Command(command) => {
+                    self.send_raw(b"+");
+                    self.handle(&command, debugger, machine)
+                }
+                Packet::Corrupted => {
+                    self.send_raw(b"-");
+                    None
+                }
+            };
+            if let Some(reply) = reply {
+                self.send(&reply);
+            }
+        }
+
+        // the debugger stopped the machine, at a breakpoint or a fault
+        if self.running && debugger.paused() {
+            self.running = false;
+            self.send(&stop_reply(signal(machine)));
+        }
+    }
+
+    fn accept(&mut self, debugger: &mut Debugger, machine: &Chip8) {
+        match self.listener.accept() {
+            Ok((client, address)) => {
+                if let Err(e) = client.set_nonblocking(true) {
+                    tracing::error!("Failed to set up the connection to gdb: {}", e);
+                    return;
+                }
+                tracing::info!("gdb connected from {}", address);
+                // gdb expects the machine to be stopped, once it attaches
+                debugger.pause(machine);
+                self.client = Some(client);
+                self.received.clear();
+                self.running = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => tracing::error!("Failed to accept gdb: {}", e),
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        tracing::info!("gdb disconnected: {}", reason);
+        self.client = None;
+        self.running = false;
+    }
+
+    /// Takes the next complete packet out of what was received
+    fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            match self.received.first()? {
+                0x03 => {
+                    self.received.remove(0);
+                    return Some(Packet::Interrupt);
+                }
+                b'$' => break,
+                // acks, and any noise before the packet
+                _ => {
+                    self.received.remove(0);
+                }
+            }
+        }
+        let end = self.received.iter().position(|&byte| byte == b'#')?;
+        if self.received.len() < end + 3 {
+            return None;
+        }
+        let packet: Vec<u8> = self.received.drain(..end + 3).collect();
+        let body = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if checksum != Some(checksum_of(body)) {
+            return Some(Packet::Corrupted);
+        }
+        Some(Packet::Command(String::from_utf8_lossy(&unescape(body)).into_owned()))
+    }
+
+    /// The reply to a packet, `None` for the ones that are answered only when the machine stops
+    fn handle(
+        &mut self,
+        command: &str,
+        debugger: &mut Debugger,
+        machine: &mut Chip8,
+    ) -> Option<String> {
+        let (kind, args) = command.split_at(command.chars().next().map_or(0, char::len_utf8));
+        Some(match kind {
+            "?" => stop_reply(signal(machine)),
+            "g" => (0..REGISTERS)
+                .map(|n| to_hex(&read_register(machine, n)))
+                .collect(),
+            "G" => match from_hex(args) {
+                Some(bytes) => {
+                    let mut bytes = bytes.as_slice();
+                    for n in 0..REGISTERS {
+                        let size = read_register(machine, n).len();
+                        let Some((value, rest)) = bytes.split_at_checked(size) else {
+                            break;
+                        };
+                        write_register(machine, n, value);
+                        bytes = rest;
+                    }
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS => to_hex(&read_register(machine, n)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, from_hex(value)?))
+                });
+                match parsed {
+                    Some((n, value)) if n < REGISTERS => {
+                        write_register(machine, n, &value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_range(args).and_then(|range| read_memory(machine, range)) {
+                Some(bytes) => to_hex(&bytes),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+                match parsed {
+                    Some((range, data))
+                        if range.end <= machine.memory().len() && data.len() == range.len() =>
+                    {
+                        for (address, value) in range.zip(data) {
+                            machine.set_memory(address, value);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => match parse_point(args) {
+                // software breakpoints, the length is always 2 bytes anyway
+                Some((0, address, _)) => {
+                    if kind == "Z" {
+                        debugger.add_breakpoint(address);
+                    } else {
+                        debugger.remove_breakpoint(address);
+                    }
+                    "OK".to_string()
+                }
+                Some((point @ 2..=4, address, len)) => {
+                    let watch = match point {
+                        2 => Watch::Write,
+                        3 => Watch::Read,
+                        _ => Watch::Access,
+                    };
+                    match address.checked_add(len.max(1)) {
+                        Some(end) if kind == "Z" => {
+                            debugger.add_watchpoint(address..end, watch);
+                            "OK".to_string()
+                        }
+                        Some(end) => {
+                            debugger.remove_watchpoint(address..end, watch);
+                            "OK".to_string()
+                        }
+                        None => "E01".to_string(),
+                    }
+                }
+                // hardware breakpoints arent any different, but gdb doesnt need them
+                Some(_) => String::new(),
+                None => "E01".to_string(),
+            },
+            "s" => {
+                let signal = match debugger.step(machine) {
+                    Ok(()) => SIGTRAP,
+                    Err(_) => signal(machine),
+                };
+                stop_reply(signal)
+            }
+            // `bs` and `bc`, the reverse step and continue
+            "b" => {
+                let reversed = match args {
+                    "s" => debugger.reverse_step(machine).is_ok(),
+                    "c" => debugger.reverse_continue(machine).is_ok(),
+                    _ => return Some(String::new()),
+                };
+                match reversed {
+                    true => stop_reply(SIGTRAP),
+                    // tells gdb there is nothing older to go back to
+                    false => format!("T{SIGTRAP:02x}replaylog:begin;"),
+                }
+            }
+            "c" => {
+                debugger.resume(machine);
+                self.running = true;
+                return None;
+            }
+            "D" => {
+                debugger.resume(machine);
+                self.send("OK");
+                self.disconnect("gdb detached");
+                return None;
+            }
+            "k" => {
+                self.disconnect("gdb killed the session");
+                return None;
+            }
+            "H" => "OK".to_string(),
+            _ => Self::query(command),
+        })
+    }
+
+    /// Everything that only asks about the stub
+    fn query(command: &str) -> String {
+        if command.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;ReverseStep+;ReverseContinue+".to_string();
+        }
+        if let Some(range) = command.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Ok(offset), Ok(len)) = (
+                usize::from_str_radix(offset, 16),
+                usize::from_str_radix(len, 16),
+            ) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = offset.saturating_add(len).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            return format!("{more}{}", &TARGET_XML[start..end]);
+        }
+        match command {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            // everything else is not supported, which gdb expects as an empty reply
+            _ => String::new(),
+        }
+    }
+
+    fn send(&mut self, reply: &str) {
+        let reply = escape(reply);
+        let packet = format!("${reply}#{:02x}", checksum_of(reply.as_bytes()));
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        // the replies are small, the socket buffer has room for them
+        if let Err(e) = client.write_all(bytes) {
+            self.disconnect(&e.to_string());
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Packet {
+    Command(String),
+    /// Ctrl-C, sent outside of any packet
+    Interrupt,
+    Corrupted,
+}
+
+/// Why the machine is stopped, a fault or just a trap
+fn signal(machine: &Chip8) -> u8 {
+    match machine.fault().map(|fault| &fault.kind) {
+        Some(ExecutionErrorKind::UnknownOpcode) => SIGILL,
+        Some(_) => SIGSEGV,
+        None => SIGTRAP,
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{signal:02x}")
+}
+
+fn read_register(machine: &Chip8, n: usize) -> Vec<u8> {
+    match n {
+        0..=15 => vec![machine.registers()[n]],
+        16 => machine.i_register().to_le_bytes().to_vec(),
+        17 => (machine.program_counter() as u16).to_le_bytes().to_vec(),
+        18 => vec![machine.stack().len() as u8],
+        19 => vec![machine.delay_timer()],
+        _ => vec![machine.sound_timer()],
+    }
+}
+
+/// SP cant be written, the stack is changed only by calls and returns
+fn write_register(machine: &mut Chip8, n: usize, value: &[u8]) {
+    let byte = value.first().copied().unwrap_or_default();
+    let word = || u16::from_le_bytes([byte, value.get(1).copied().unwrap_or_default()]);
+    match n {
+        0..=15 => machine.set_register(n, byte),
+        16 => machine.set_i_register(word()),
+        17 => machine.set_program_counter(word() as usize),
+        19 => machine.set_delay_timer(byte),
+        20 => machine.set_sound_timer(byte),
+        _ => {}
+    }
+}
+
+/// `TYPE,ADDR,KIND` of the `Z`/`z` packets, the kind is the length for watchpoints
+fn parse_point(text: &str) -> Option<(u8, usize, usize)> {
+    let mut parts = text.split(',');
+    let point = parts.next()?.parse().ok()?;
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((point, address, len))
+}
+
+/// `ADDR,LEN` in hex
+fn parse_range(text: &str) -> Option<Range<usize>> {
+    let (address, len) = text.split_once(',')?;
+    let address = usize::from_str_radix(address, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    Some(address..address.checked_add(len)?)
+}
+
+fn read_memory(machine: &Chip8, range: Range<usize>) -> Option<Vec<u8>> {
+    let memory = machine.memory();
+    (range.end <= memory.len()).then(|| range.map(|address| memory[address]).collect())
+}
+
+/// `$`, `#`, `}` and `*` go as a `}` and the byte XORed with 0x20,
+/// the checksum is of the escaped bytes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '$' | '#' | '}' | '*') {
+            escaped.push('}');
+            escaped.push((c as u8 ^ 0x20) as char);
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn unescape(bytes: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => unescaped.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            _ => unescaped.push(byte),
+        }
+    }
+    unescaped
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+use chip_8_emulator::{
+    cpu::ExecutionErrorKind,
+    debugger::{Debugger, Watch},
+    Chip8,
+};
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Range,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stub(received: &[u8]) -> GdbStub {
+        let mut stub = GdbStub::bind(0).unwrap();
+        stub.received = received.to_vec();
+        stub
+    }
+
+    fn command(text: &str) -> Option<Packet> {
+        Some(Packet::Command(text.to_string()))
+    }
+
+    #[test]
+    fn a_good_checksum_gives_the_command() {
+        let mut stub = stub(b"+$g#67");
+        assert_eq!(stub.next_packet(), command("g"));
+        assert_eq!(stub.next_packet(), None);
+        assert!(stub.received.is_empty());
+    }
+
+    #[test]
+    fn a_bad_checksum_is_corrupted() {
+        let mut stub = stub(b"$g#00$g#67");
+        assert_eq!(stub.next_packet(), Some(Packet::Corrupted));
+        assert_eq!(stub.next_packet(), command("g"));
+        let mut stub = self::stub(b"$g#zz");
+        assert_eq!(stub.next_packet(), Some(Packet::Corrupted));
+    }
+
+    #[test]
+    fn packets_wait_for_the_checksum() {
+        let mut stub = stub(b"$m200,2#");
+        assert_eq!(stub.next_packet(), None);
+        stub.received.extend(b"5");
+        assert_eq!(stub.next_packet(), None);
+        stub.received.extend(b"d\x03");
+        assert_eq!(stub.next_packet(), command("m200,2"));
+        assert_eq!(stub.next_packet(), Some(Packet::Interrupt));
+    }
+
+    #[test]
+    fn escaped_bytes() {
+        assert_eq!(escape("a#b*c}$"), "a}\x03b}\nc}]}\x04");
+        assert_eq!(unescape(escape("a#b*c}$").as_bytes()), b"a#b*c}$");
+
+        // the checksum is of the bytes as sent, and an escaped `#` doesnt end the packet
+        let body = b"X200,1:}\x03";
+        let mut packet = b"$".to_vec();
+        packet.extend(body);
+        packet.extend(format!("#{:02x}", checksum_of(body)).bytes());
+        assert_eq!(stub(&packet).next_packet(), command("X200,1:#"));
+    }
+
+    #[test]
+    fn memory_reads_and_writes() {
+        let mut stub = stub(b"");
+        let mut debugger = Debugger::new(true);
+        let mut machine = Chip8::new(&[0x12, 0x34]).unwrap();
+        let mut handle = |text: &str| stub.handle(text, &mut debugger, &mut machine);
+        assert_eq!(handle("m200,2").as_deref(), Some("1234"));
+        assert_eq!(handle("M200,2:abcd").as_deref(), Some("OK"));
+        assert_eq!(handle("m200,2").as_deref(), Some("abcd"));
+        // past the end of the memory, and past the end of the addresses
+        assert_eq!(handle("mfff,2").as_deref(), Some("E01"));
+        assert_eq!(handle("mffffffffffffffff,2").as_deref(), Some("E01"));
+        assert_eq!(handle("Mffffffffffffffff,2:abcd").as_deref(), Some("E01"));
+        assert_eq!(handle("Z2,ffffffffffffffff,2").as_deref(), Some("E01"));
+        assert_eq!(handle("Z2,300,2").as_deref(), Some("OK"));
+    }
+}
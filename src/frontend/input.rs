@@ -0,0 +1,28 @@
+/// The keyboard layout of the emulator
+pub fn from_key_code(key: KeyCode) -> Option<Key> {
+    Some(match key {
+        KeyCode::Numpad0 => Key::Zero,
+        KeyCode::Numpad1 => Key::One,
+        KeyCode::Numpad2 => Key::Two,
+        KeyCode::Numpad3 => Key::Three,
+        KeyCode::Numpad4 => Key::Four,
+        KeyCode::Numpad5 => Key::Five,
+        KeyCode::Numpad6 => Key::Six,
+        KeyCode::Numpad7 => Key::Seven,
+        KeyCode::Numpad8 => Key::Eight,
+        KeyCode::Numpad9 => Key::Nine,
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyB => Key::B,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyD => Key::D,
+        KeyCode::KeyE => Key::E,
+        KeyCode::KeyF => Key::F,
+        _ => {
+            tracing::error!("Unknown key: {:?}", key);
+            return None;
+        }
+    })
+}
+
+use chip_8_emulator::input::Key;
+use winit::keyboard::KeyCode;
@@ -0,0 +1,401 @@
+//! The debug side panels, drawn into the pixels frame right of the screen.
+//!
+//! F10 toggles the one with the registers, stack, timers, pressed keys
+//! and the disassembly around the PC, F9 the one with the memory,
+//! F8 the heatmap of the coverage.
+
+/// The width of a panel, in buffer pixels. It is as tall as the screen.
+pub const PANEL_WIDTH: usize = 160;
+
+/// The glyphs are 3x5, with a pixel of spacing around them
+const GLYPH_SIZE: (usize, usize) = (4, 6);
+const MARGIN: usize = 2;
+
+const BACKGROUND_COLOR: [u8; 4] = [0x2b, 0x1a, 0x06, 0xff];
+const TEXT_COLOR: [u8; 4] = [0xe8, 0xf2, 0x55, 0xff];
+/// The labels, and everything thats off
+const DIM_COLOR: [u8; 4] = [0xb5, 0x83, 0x16, 0xff];
+/// The instruction at the PC
+const HIGHLIGHT_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+/// The byte at I
+const I_COLOR: [u8; 4] = [0x55, 0xd0, 0xf2, 0xff];
+const READ_COLOR: [u8; 4] = [0x70, 0xe0, 0x70, 0xff];
+const WRITE_COLOR: [u8; 4] = [0xff, 0x60, 0x40, 0xff];
+
+/// How many instructions are shown before the one at the PC
+const DISASSEMBLY_BEFORE: usize = 3;
+
+/// The memory panel shows this many bytes per line
+const MEMORY_LINE: usize = 8;
+/// How many lines of the memory panel are before the one with the PC
+const MEMORY_LINES_BEFORE: usize = 6;
+/// Rows of the sprite at I
+const SPRITE_HEIGHT: usize = 15;
+const SPRITE_SCALE: usize = 2;
+
+/// Bytes per line of the coverage heatmap
+const HEATMAP_LINE: usize = 64;
+/// Pixels of a byte in the heatmap, a side
+const HEATMAP_CELL: usize = 2;
+/// The bytes that were never touched
+const UNTOUCHED_COLOR: [u8; 4] = [0x45, 0x30, 0x10, 0xff];
+
+/// A frame of RGBA pixels
+struct Canvas<'a> {
+    frame: &'a mut [u8],
+    width: usize,
+    /// Where the panel starts
+    left: usize,
+}
+
+impl<'a> Canvas<'a> {
+    /// Clears the panel starting at `left`, in a frame that is `width` pixels wide
+    fn new(frame: &'a mut [u8], width: usize, left: usize) -> Self {
+        for row in frame.chunks_exact_mut(width * 4) {
+            for pixel in row[left * 4..(left + PANEL_WIDTH) * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&BACKGROUND_COLOR);
+            }
+        }
+        Canvas { frame, width, left }
+    }
+
+    fn height(&self) -> usize {
+        self.frame.len() / 4 / self.width
+    }
+
+    /// How many lines of text fit
+    fn rows(&self) -> usize {
+        (self.height() - MARGIN) / GLYPH_SIZE.1
+    }
+
+    /// Fills a rectangle, relative to the top left of the panel
+    fn rect(&mut self, x: usize, y: usize, size: (usize, usize), color: [u8; 4]) {
+        let height = self.height();
+        for row in y..(y + size.1).min(height) {
+            for column in x..(x + size.0).min(PANEL_WIDTH) {
+                let index = (row * self.width + self.left + column) * 4;
+                self.frame[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Writes `text` at the `column`th character of the `row`th line of the panel,
+    /// whatever doesnt fit is cut off
+    fn text(&mut self, row: usize, column: usize, text: &str, color: [u8; 4]) {
+        let top = MARGIN + row * GLYPH_SIZE.1;
+        for (n, c) in text.chars().enumerate() {
+            let left = MARGIN + (column + n) * GLYPH_SIZE.0;
+            if left + GLYPH_SIZE.0 > PANEL_WIDTH || top + GLYPH_SIZE.1 > self.height() {
+                return;
+            }
+            for (dy, bits) in glyph(c).into_iter().enumerate() {
+                for dx in 0..3 {
+                    if bits & (0b100 >> dx) != 0 {
+                        self.rect(left + dx, top + dy, (1, 1), color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws the registers panel, `left` is the first column of it in a frame that is `width` pixels wide
+pub fn draw(frame: &mut [u8], width: usize, left: usize, machine: &Chip8, symbols: &Symbols) {
+    let mut canvas = Canvas::new(frame, width, left);
+    let mut row = 0;
+    let field = |canvas: &mut Canvas, row: usize, column: usize, name: &str, value: String| {
+        canvas.text(row, column, name, DIM_COLOR);
+        canvas.text(row, column + name.len() + 1, &value, TEXT_COLOR);
+    };
+
+    let pc = machine.program_counter();
+    field(&mut canvas, row, 0, "PC", format!("{pc:04X}"));
+    field(&mut canvas, row, 9, "I", format!("{:04X}", machine.i_register()));
+    row += 1;
+    field(&mut canvas, row, 0, "DT", format!("{:02X}", machine.delay_timer()));
+    field(&mut canvas, row, 7, "ST", format!("{:02X}", machine.sound_timer()));
+    field(&mut canvas, row, 14, "SP", machine.stack().len().to_string());
+    row += 1;
+
+    for (n, chunk) in machine.registers().chunks(4).enumerate() {
+        for (m, value) in chunk.iter().enumerate() {
+            let name = format!("V{:X}", n * 4 + m);
+            field(&mut canvas, row, m * 6, &name, format!("{value:02X}"));
+        }
+        row += 1;
+    }
+
+    // the innermost calls, 2 lines of them
+    canvas.text(row, 0, "STACK", DIM_COLOR);
+    let stack = machine.stack();
+    let shown = &stack[stack.len().saturating_sub(14)..];
+    for (n, chunk) in shown.rchunks(7).enumerate() {
+        let addresses: Vec<_> = chunk.iter().rev().map(|a| format!("{a:04X}")).collect();
+        canvas.text(row + 1 + n, 1, &addresses.join(" "), TEXT_COLOR);
+    }
+    row += 3;
+
+    canvas.text(row, 0, "KEYS", DIM_COLOR);
+    for n in 0..16u8 {
+        let pressed = Key::try_from(n).is_ok_and(|key| machine.inputs().is_pressed(key));
+        let color = if pressed { HIGHLIGHT_COLOR } else { DIM_COLOR };
+        canvas.text(row, 5 + n as usize * 2, &format!("{n:X}"), color);
+    }
+    row += 2;
+
+    let memory = machine.memory();
+    let word = |address: usize| {
+        (address + 1 < memory.len())
+            .then(|| u16::from_be_bytes([memory[address], memory[address + 1]]))
+    };
+    let label = |target: u16| symbols.name(target as usize).map(str::to_string);
+    // going back is a guess, assuming 2 byte instructions
+    let mut address = pc.saturating_sub(DISASSEMBLY_BEFORE * 2);
+    while row < canvas.rows() {
+        let Some(opcode) = word(address) else {
+            break;
+        };
+        if let Some(name) = symbols.name(address) {
+            canvas.text(row, 1, &format!("{name}:"), DIM_COLOR);
+            row += 1;
+            if row >= canvas.rows() {
+                break;
+            }
+        }
+        let instruction = Instruction::decode(opcode);
+        let text = match instruction {
+            Ok(instruction) => instruction.format(Syntax::Classic, word(address + 2), &label),
+            Err(_) => "??".to_string(),
+        };
+        let (marker, color) = match address == pc {
+            true => (">", HIGHLIGHT_COLOR),
+            false => (" ", TEXT_COLOR),
+        };
+        canvas.text(row, 0, marker, color);
+        canvas.text(row, 1, &format!("{address:04X}"), DIM_COLOR);
+        canvas.text(row, 6, &text, color);
+        address += instruction.map_or(2, |instruction| instruction.size());
+        row += 1;
+    }
+}
+
+/// Draws the memory panel, like [`draw`].
+///
+/// The bytes around the PC, colored by what was recently done to them,
+/// and the sprite at I on the right.
+pub fn draw_memory(
+    frame: &mut [u8],
+    width: usize,
+    left: usize,
+    machine: &Chip8,
+    accesses: &AccessHistory,
+) {
+    let mut canvas = Canvas::new(frame, width, left);
+    let memory = machine.memory();
+    let pc = machine.program_counter();
+    let i = machine.i_register() as usize;
+
+    canvas.text(0, 0, "MEM", DIM_COLOR);
+    canvas.text(0, 4, "PC", HIGHLIGHT_COLOR);
+    canvas.text(0, 7, "I", I_COLOR);
+    canvas.text(0, 9, "READ", READ_COLOR);
+    canvas.text(0, 14, "WRITE", WRITE_COLOR);
+
+    let lines = canvas.rows() - 1;
+    let last_start = memory.len().saturating_sub(lines * MEMORY_LINE);
+    let start = (pc / MEMORY_LINE)
+        .saturating_sub(MEMORY_LINES_BEFORE)
+        .saturating_mul(MEMORY_LINE)
+        .min(last_start);
+    for line in 0..lines {
+        let address = start + line * MEMORY_LINE;
+        if address >= memory.len() {
+            break;
+        }
+        canvas.text(line + 1, 0, &format!("{address:04X}"), DIM_COLOR);
+        for n in 0..MEMORY_LINE.min(memory.len() - address) {
+            let address = address + n;
+            let color = match accesses.recent(address, machine.cycles()) {
+                _ if address == pc || address == pc + 1 => HIGHLIGHT_COLOR,
+                _ if address == i => I_COLOR,
+                Some(AccessKind::Write) => WRITE_COLOR,
+                Some(AccessKind::Read) => READ_COLOR,
+                None => TEXT_COLOR,
+            };
+            canvas.text(line + 1, 5 + n * 3, &format!("{:02X}", memory[address]), color);
+        }
+    }
+
+    // the sprite, right of the bytes
+    let column = 5 + MEMORY_LINE * 3;
+    canvas.text(0, column, "AT I", I_COLOR);
+    let x = MARGIN + column * GLYPH_SIZE.0;
+    for row in 0..SPRITE_HEIGHT {
+        if i + row >= memory.len() {
+            break;
+        }
+        let byte = memory[i + row];
+        for bit in 0..8 {
+            let color = match byte & (0x80 >> bit) != 0 {
+                true => TEXT_COLOR,
+                false => DIM_COLOR,
+            };
+            let y = MARGIN + GLYPH_SIZE.1 + row * SPRITE_SCALE;
+            canvas.rect(
+                x + bit * SPRITE_SCALE,
+                y,
+                (SPRITE_SCALE, SPRITE_SCALE),
+                color,
+            );
+        }
+    }
+}
+
+/// Draws the coverage panel, like [`draw`].
+///
+/// A square per byte of the memory, from the start of the rom on, or around the PC if it is past
+/// what fits. Executed bytes go from dim to bright the more they ran, on a log scale so that
+/// a hot loop doesnt wash out the rest, then written ones are red and read ones green.
+pub fn draw_coverage(
+    frame: &mut [u8],
+    width: usize,
+    left: usize,
+    machine: &Chip8,
+    coverage: &Coverage,
+) {
+    let mut canvas = Canvas::new(frame, width, left);
+    let memory = machine.memory();
+    let pc = machine.program_counter();
+
+    canvas.text(0, 0, "COV", DIM_COLOR);
+    canvas.text(0, 4, "EXEC", TEXT_COLOR);
+    canvas.text(0, 9, "READ", READ_COLOR);
+    canvas.text(0, 14, "WRITE", WRITE_COLOR);
+
+    let top = MARGIN + GLYPH_SIZE.1;
+    let x = MARGIN + 5 * GLYPH_SIZE.0;
+    let lines = (canvas.height() - top) / HEATMAP_CELL;
+    let first = ORIGIN / HEATMAP_LINE;
+    let pc_line = pc / HEATMAP_LINE;
+    let start = match pc_line >= first + lines {
+        true => pc_line - lines / 2,
+        false => first,
+    };
+    let max = (coverage.max_executions() as f32).ln_1p();
+    // an address every 2 lines of text
+    let labelled = 2 * GLYPH_SIZE.1 / HEATMAP_CELL;
+    for line in 0..lines {
+        let address = (start + line) * HEATMAP_LINE;
+        if address >= memory.len() {
+            break;
+        }
+        if line % labelled == 0 {
+            canvas.text(1 + line * HEATMAP_CELL / GLYPH_SIZE.1, 0, &format!("{address:04X}"), DIM_COLOR);
+        }
+        for n in 0..HEATMAP_LINE.min(memory.len() - address) {
+            let address = address + n;
+            let executions = coverage.executions(address);
+            let color = if address == pc || address == pc + 1 {
+                HIGHLIGHT_COLOR
+            } else if executions > 0 {
+                let heat = (executions as f32).ln_1p() / max;
+                mix(DIM_COLOR, TEXT_COLOR, heat)
+            } else if coverage.writes(address) > 0 {
+                WRITE_COLOR
+            } else if coverage.reads(address) > 0 {
+                READ_COLOR
+            } else {
+                UNTOUCHED_COLOR
+            };
+            canvas.rect(
+                x + n * HEATMAP_CELL,
+                top + line * HEATMAP_CELL,
+                (HEATMAP_CELL, HEATMAP_CELL),
+                color,
+            );
+        }
+    }
+}
+
+/// `from` at 0, `to` at 1
+fn mix(from: [u8; 4], to: [u8; 4], t: f32) -> [u8; 4] {
+    let t = t.clamp(0., 1.);
+    let mut color = from;
+    for (c, (&from, &to)) in color.iter_mut().zip(from.iter().zip(&to)) {
+        *c = (from as f32 + (to as f32 - from as f32) * t) as u8;
+    }
+    color
+}
+
+/// The rows of a character, top to bottom, the 3 low bits are the pixels left to right.
+/// Lowercase is drawn as uppercase, except the `x` of `0x`, and anything unknown as `?`
+#[rustfmt::skip]
+fn glyph(c: char) -> [u8; 5] {
+    let c = if c == 'x' { c } else { c.to_ascii_uppercase() };
+    match c {
+        'x' => [0b000, 0b000, 0b101, 0b010, 0b101],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _   => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+use chip_8_emulator::{
+    coverage::Coverage,
+    cpu::{Instruction, Syntax},
+    disasm::ORIGIN,
+    input::Key,
+    memory::AccessKind,
+    memview::AccessHistory,
+    symbols::Symbols,
+    Chip8,
+};
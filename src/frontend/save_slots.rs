@@ -0,0 +1,42 @@
+/// The save states of a rom, kept next to it as `<rom>.state1` .. `<rom>.state4`
+pub struct SaveSlots {
+    rom_path: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new(rom_path: &Path) -> Self {
+        SaveSlots {
+            rom_path: rom_path.to_path_buf(),
+        }
+    }
+
+    /// `slot` starts from 1
+    fn path(&self, slot: usize) -> PathBuf {
+        let mut path = self.rom_path.clone().into_os_string();
+        path.push(format!(".state{slot}"));
+        path.into()
+    }
+
+    pub fn save(&self, slot: usize, machine: &Chip8) {
+        let path = self.path(slot);
+        match std::fs::write(&path, machine.save_state()) {
+            Ok(()) => tracing::info!("Saved the state to {}", path.display()),
+            Err(e) => tracing::error!("Failed to save the state to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Leaves the machine as it was, if the slot is empty or broken
+    pub fn load(&self, slot: usize, machine: &mut Chip8) {
+        let path = self.path(slot);
+        let loaded = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|state| machine.load_state(&state).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(()) => tracing::info!("Loaded the state from {}", path.display()),
+            Err(e) => tracing::error!("Failed to load the state from {}: {}", path.display(), e),
+        }
+    }
+}
+
+use chip_8_emulator::Chip8;
+use std::path::{Path, PathBuf};
@@ -0,0 +1,119 @@
+const BEEP_TIME: Duration = FRAME_TIME.saturating_sub(Duration::from_micros(50));
+/// Sample rate of the rendered XO-CHIP patterns
+const PATTERN_SAMPLE_RATE: u32 = 48_000;
+const PATTERN_VOLUME: f32 = 0.25;
+
+/// The speakers, fed one frame of sound at a time
+pub struct Sound {
+    // dropping the stream stops the sound
+    _stream: OutputStream,
+    sink: Sink,
+    /// where the pattern stopped in the last frame, so that the next one continues from there
+    pattern_position: f32,
+}
+
+impl Sound {
+    pub fn new() -> Self {
+        let (stream, stream_handle) = OutputStream::try_default().unwrap();
+        let sink = Sink::try_new(&stream_handle).unwrap();
+        tracing::info!("Sound initialized");
+        Sound {
+            _stream: stream,
+            sink,
+            pattern_position: 0.,
+        }
+    }
+
+    /// Queues the sound of the frame that just ran, if the sound timer is on
+    pub fn play_frame(&mut self, machine: &Chip8) {
+        if machine.sound_timer() == 0 {
+            return;
+        }
+        let audio = machine.audio();
+        match audio.pattern() {
+            // there is an audible click between the beeps.
+            // i tried ~increase~ decrease the beep time inbetween the loops.
+            None => self.sink.append(make_beep()),
+            Some(&pattern) => {
+                let wave = PatternWave::new(pattern, audio.playback_rate(), self.pattern_position);
+                self.pattern_position = wave.end_position();
+                self.sink.append(wave);
+            }
+        }
+    }
+}
+
+fn make_beep() -> TakeDuration<SineWave> {
+    SineWave::new(440.).take_duration(BEEP_TIME)
+}
+
+/// One frame worth of an XO-CHIP pattern, each bit is a high or a low sample
+struct PatternWave {
+    pattern: [u8; 16],
+    /// Bits of the pattern played per output sample
+    step: f32,
+    /// Position in the pattern, in bits
+    position: f32,
+    remaining_samples: usize,
+}
+
+impl PatternWave {
+    fn new(pattern: [u8; 16], playback_rate: f32, position: f32) -> Self {
+        PatternWave {
+            pattern,
+            step: playback_rate / PATTERN_SAMPLE_RATE as f32,
+            position,
+            remaining_samples: (PATTERN_SAMPLE_RATE as f32 * FRAME_TIME.as_secs_f32()) as usize,
+        }
+    }
+
+    /// The position the pattern would be at, once this frame is played
+    fn end_position(&self) -> f32 {
+        (self.position + self.step * self.remaining_samples as f32) % Audio::PATTERN_BITS as f32
+    }
+}
+
+impl Iterator for PatternWave {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.remaining_samples == 0 {
+            return None;
+        }
+        self.remaining_samples -= 1;
+        let bit = self.position as usize % Audio::PATTERN_BITS;
+        self.position = (self.position + self.step) % Audio::PATTERN_BITS as f32;
+        let high = self.pattern[bit / 8] >> (7 - bit % 8) & 0x1 == 1;
+        Some(if high {
+            PATTERN_VOLUME
+        } else {
+            -PATTERN_VOLUME
+        })
+    }
+}
+
+impl Source for PatternWave {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.remaining_samples)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        PATTERN_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(FRAME_TIME)
+    }
+}
+
+use super::FRAME_TIME;
+use chip_8_emulator::{audio::Audio, Chip8};
+use rodio::{
+    source::{SineWave, Source, TakeDuration},
+    OutputStream, Sink,
+};
+use std::time::Duration;
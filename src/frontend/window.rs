@@ -0,0 +1,396 @@
+/// The size of one pixel on CHIP-8 screen in the current display.
+const PIXEL_SCALE: (Upixel, Upixel) = (20, 20);
+
+/// Big enough for both the resolutions
+const BUFFER_SIZE: (Upixel, Upixel) = HIRES_SCREEN_SIZE;
+
+/// With the debug overlay, the screen is drawn at twice the resolution,
+/// so that the text next to it is small enough
+const OVERLAY_SCREEN_SCALE: Upixel = 2;
+
+const WIDTH: Upixel = SCREEN_SIZE.0 * PIXEL_SCALE.0;
+const HEIGHT: Upixel = SCREEN_SIZE.1 * PIXEL_SCALE.1;
+
+const ON_PIXEL_COLOR: [u8; 4] = [0xe8, 0xf2, 0x55, 0xff];
+const OFF_PIXEL_COLOR: [u8; 4] = [0xb5, 0x83, 0x16, 0xff];
+/// Pixels only on the second XO-CHIP plane
+const SECOND_PLANE_COLOR: [u8; 4] = [0x5c, 0x2e, 0x0b, 0xff];
+/// Pixels on both the XO-CHIP planes
+const BOTH_PLANES_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// Indexed by the value of the pixel, one bit per plane
+const PALETTE: [[u8; 4]; 4] = [
+    OFF_PIXEL_COLOR,
+    ON_PIXEL_COLOR,
+    SECOND_PLANE_COLOR,
+    BOTH_PLANES_COLOR,
+];
+
+const TITLE: &str = "CHIP-8 Emulator";
+
+pub fn main_thread(
+    machine: Chip8,
+    debugger: Debugger,
+    stdin: Receiver<String>,
+    gdb: Option<GdbStub>,
+    save_slots: SaveSlots,
+    rewind: Rewind,
+) -> Debugger {
+    // safety: unwrap, as for any failures, we want to panic
+
+    let event_loop = EventLoop::new().unwrap(); // talk with the OS to create a window
+
+    let mut app = App::new(machine, debugger, stdin, gdb, save_slots, rewind);
+
+    event_loop.run_app(&mut app).unwrap();
+
+    // anything done here doesnt execute until the event loop is closed
+    // i.e., the app is closed.
+    app.debugger
+}
+
+struct App {
+    // window needs to be stored, as dropping it means closing the window
+    window: Option<Window>,
+    machine: Chip8,
+    pixels: Option<Pixels>,
+    sound: Sound,
+    /// When the next frame is due
+    next_frame: Instant,
+    /// Runs the machine, gets its commands from the stdin
+    debugger: Debugger,
+    stdin: Receiver<String>,
+    /// Shares the debugger with the stdin
+    gdb: Option<GdbStub>,
+    /// F1..F4 load, Shift+F1..F4 save
+    save_slots: SaveSlots,
+    modifiers: ModifiersState,
+    rewind: Rewind,
+    /// Backspace is held, the machine goes back a frame every frame
+    rewinding: bool,
+    /// Whether the title is currently showing a fault
+    showing_fault: bool,
+    /// F10 shows the debug panel next to the screen
+    overlay: bool,
+    /// F9 shows the memory panel, right of the debug panel if thats shown too
+    memory_overlay: bool,
+    /// F8 shows the coverage heatmap, right of the other panels
+    coverage_overlay: bool,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        info!("Window has been resumed/ initialized, probably initialized for the first time");
+
+        let window = event_loop
+            .create_window(
+                WindowAttributes::default()
+                    .with_title(TITLE)
+                    .with_min_inner_size(PhysicalSize::new(WIDTH, HEIGHT))
+                    .with_resizable(true),
+            )
+            .expect("Failed to create window");
+        let surface_texture = SurfaceTexture::new(WIDTH, HEIGHT, &window);
+
+        let (width, height) = self.buffer_size();
+        self.pixels = Some(Pixels::new(width, height, surface_texture).unwrap());
+        self.window = Some(window);
+        let _ = self.render_mem();
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        use KeyCode::*;
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(physical_size) => {
+                if let Some(pixels) = self.pixels.as_mut() {
+                    if let Err(e) = pixels.resize_surface(physical_size.width, physical_size.height)
+                    {
+                        // probably means the window is minimized
+                        tracing::error!("Failed to resize surface: {}", e);
+                        self.pixels = None;
+                    }
+                } else {
+                    tracing::warn!("Empty pixels, trying to re-initialize");
+                    let surface_texture =
+                        SurfaceTexture::new(WIDTH, HEIGHT, self.window.as_ref().unwrap());
+                    let (width, height) = self.buffer_size();
+                    self.pixels = Some(Pixels::new(width, height, surface_texture).unwrap());
+                }
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match key {
+                KeyCode::Escape => tracing::info!("Escape key {:?}", state),
+                F5 if state.is_pressed() => self.machine.reset(),
+                F12 if state.is_pressed() => self.toggle_debugging(),
+                F10 if state.is_pressed() => {
+                    self.overlay = !self.overlay;
+                    self.resize_buffer();
+                }
+                F9 if state.is_pressed() => {
+                    self.memory_overlay = !self.memory_overlay;
+                    self.resize_buffer();
+                }
+                F8 if state.is_pressed() => {
+                    self.coverage_overlay = !self.coverage_overlay;
+                    self.resize_buffer();
+                }
+                Backspace => self.rewinding = state.is_pressed(),
+                F1 | F2 | F3 | F4 if state.is_pressed() => {
+                    let slot = match key {
+                        F1 => 1,
+                        F2 => 2,
+                        F3 => 3,
+                        _ => 4,
+                    };
+                    if self.modifiers.shift_key() {
+                        self.save_slots.save(slot, &self.machine);
+                    } else {
+                        self.save_slots.load(slot, &mut self.machine);
+                    }
+                }
+                Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7
+                | Numpad8 | Numpad9 | KeyA | KeyB | KeyC | KeyD | KeyE | KeyF => {
+                    if let Some(key) = super::input::from_key_code(key) {
+                        if state.is_pressed() {
+                            self.machine.press_key(key);
+                        } else {
+                            self.machine.release_key(key);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        match cause {
+            StartCause::Init => {
+                self.next_frame = Instant::now();
+                if self.debugger.paused() {
+                    println!("{}", self.debugger.pause(&self.machine));
+                }
+            }
+            StartCause::ResumeTimeReached { .. } => {
+                self.next_frame += FRAME_TIME;
+                // dont try to catch up on the missed frames, e.g. after the window was dragged
+                let now = Instant::now();
+                if self.next_frame < now {
+                    self.next_frame = now;
+                }
+                self.frame(event_loop);
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+    }
+}
+
+impl App {
+    fn new(
+        machine: Chip8,
+        debugger: Debugger,
+        stdin: Receiver<String>,
+        gdb: Option<GdbStub>,
+        save_slots: SaveSlots,
+        rewind: Rewind,
+    ) -> Self {
+        Self {
+            window: None,
+            pixels: None,
+            machine,
+            sound: Sound::new(),
+            next_frame: Instant::now(),
+            debugger,
+            stdin,
+            gdb,
+            save_slots,
+            modifiers: ModifiersState::empty(),
+            rewind,
+            rewinding: false,
+            showing_fault: false,
+            overlay: false,
+            memory_overlay: false,
+            coverage_overlay: false,
+        }
+    }
+
+    /// Runs a frame worth of instructions, ticks the timers, plays the sound and draws the screen.
+    /// Nothing runs while the debugger has the machine stopped,
+    /// and while rewinding, goes back a frame instead.
+    fn frame(&mut self, event_loop: &ActiveEventLoop) {
+        for line in self.stdin.try_iter() {
+            println!("{}", self.debugger.command(&line, &mut self.machine));
+        }
+        if let Some(gdb) = self.gdb.as_mut() {
+            gdb.poll(&mut self.debugger, &mut self.machine);
+        }
+
+        if self.rewinding {
+            // stays on the oldest frame, once there is nothing more to rewind
+            self.rewind.pop(&mut self.machine);
+        } else if !self.debugger.paused() {
+            if let Some(stop) = self.debugger.run_frame(&mut self.machine) {
+                println!("{stop}");
+            }
+            self.sound.play_frame(&self.machine);
+            self.rewind.push(&self.machine);
+        }
+
+        if self.machine.exited() {
+            info!("The rom exited");
+            event_loop.exit();
+            return;
+        }
+        self.show_fault();
+        let _ = self.render_mem();
+    }
+
+    /// Puts the fault of a halted CPU into the window title
+    fn show_fault(&mut self) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        match (self.machine.fault(), self.showing_fault) {
+            (Some(fault), false) => {
+                window.set_title(&format!("{TITLE} - {fault} (F5: reset, F12: debug)"));
+                self.showing_fault = true;
+            }
+            (None, true) => {
+                window.set_title(TITLE);
+                self.showing_fault = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Stops/continues the machine in the debugger
+    fn toggle_debugging(&mut self) {
+        if self.debugger.paused() {
+            self.debugger.resume(&self.machine);
+            println!("Continuing");
+        } else {
+            println!("{}", self.debugger.pause(&self.machine));
+        }
+    }
+
+    /// Fits the buffer to the panels that are shown
+    fn resize_buffer(&mut self) {
+        let (width, height) = self.buffer_size();
+        if let Some(pixels) = self.pixels.as_mut() {
+            if let Err(e) = pixels.resize_buffer(width, height) {
+                tracing::error!("Failed to resize the buffer for the overlay: {}", e);
+                self.overlay = false;
+                self.memory_overlay = false;
+                self.coverage_overlay = false;
+                return;
+            }
+        }
+        let _ = self.render_mem();
+    }
+
+    /// Number of debug panels shown
+    fn panels(&self) -> usize {
+        self.overlay as usize + self.memory_overlay as usize + self.coverage_overlay as usize
+    }
+
+    /// The part of the buffer that has the CHIP-8 screen
+    fn screen_size(&self) -> (Upixel, Upixel) {
+        match self.panels() > 0 {
+            true => (
+                BUFFER_SIZE.0 * OVERLAY_SCREEN_SCALE,
+                BUFFER_SIZE.1 * OVERLAY_SCREEN_SCALE,
+            ),
+            false => BUFFER_SIZE,
+        }
+    }
+
+    /// The screen, and the debug panels right of it
+    fn buffer_size(&self) -> (Upixel, Upixel) {
+        let (width, height) = self.screen_size();
+        let panels = (self.panels() * overlay::PANEL_WIDTH) as Upixel;
+        (width + panels, height)
+    }
+
+    fn render_mem(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let screen_size = self.screen_size();
+        let buffer_width = self.buffer_size().0 as usize;
+        let pixels = self.pixels.as_mut().ok_or("Pixels not initialized")?;
+        let frame = pixels.frame_mut();
+        let graphics = self.machine.graphics_memory();
+        // the buffer is always in the high resolution, low resolution pixels get stretched
+        let scale = (
+            screen_size.0 as usize / graphics.width(),
+            screen_size.1 as usize / graphics.height(),
+        );
+        for (index, display_pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let x = index % buffer_width;
+            if x >= screen_size.0 as usize {
+                continue;
+            }
+            let y = index / buffer_width / scale.1;
+            display_pixel.copy_from_slice(&PALETTE[graphics.pixel(x / scale.0, y) as usize]);
+        }
+        let mut left = screen_size.0 as usize;
+        if self.overlay {
+            overlay::draw(
+                frame,
+                buffer_width,
+                left,
+                &self.machine,
+                self.debugger.symbols(),
+            );
+            left += overlay::PANEL_WIDTH;
+        }
+        if self.memory_overlay {
+            let accesses = self.debugger.accesses();
+            overlay::draw_memory(frame, buffer_width, left, &self.machine, accesses);
+            left += overlay::PANEL_WIDTH;
+        }
+        if self.coverage_overlay {
+            let coverage = self.debugger.coverage();
+            overlay::draw_coverage(frame, buffer_width, left, &self.machine, coverage);
+        }
+
+        pixels.render()?;
+        Ok(())
+    }
+}
+
+use std::{sync::mpsc::Receiver, time::Instant};
+
+use super::{gdb::GdbStub, overlay, save_slots::SaveSlots, sound::Sound, FRAME_TIME};
+use chip_8_emulator::{
+    debugger::Debugger,
+    graphics::{Upixel, HIRES_SCREEN_SIZE, SCREEN_SIZE},
+    rewind::Rewind,
+    Chip8,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{KeyEvent, StartCause, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use pixels::{Pixels, SurfaceTexture};
+
+use tracing::info;
@@ -1,194 +1,172 @@
-/// The size of one pixel on CHIP-8 screen in the current display.
-const PIXEL_SCALE: (Upixel, Upixel) = (20, 20);
 /// the emulator had a window size of 64x32 pixels
 pub const SCREEN_SIZE: (Upixel, Upixel) = (64, 32);
+/// SUPER-CHIP's high resolution mode, enabled by `00FF`
+pub const HIRES_SCREEN_SIZE: (Upixel, Upixel) = (128, 64);
 
-const WIDTH: Upixel = SCREEN_SIZE.0 * PIXEL_SCALE.0;
-const HEIGHT: Upixel = SCREEN_SIZE.1 * PIXEL_SCALE.1;
+/// The complete memory assosciated to graphics
+///
+/// Only holds the pixels of the active resolution,
+/// switching the resolution clears the screen.
+///
+/// XO-CHIP has 2 bitplanes, each pixel holds a bit for each of them,
+/// so its value is one of the 4 colors. The usual roms only ever draw on the first plane.
+pub struct GraphicsMemory {
+    pixels: Vec<u8>,
+    hires: bool,
+    /// The planes that get drawn/cleared/scrolled, as a bitmask, set by `FN01`
+    selected_planes: u8,
+}
 
-const ON_PIXEL_COLOR: [u8; 4] = [0xe8, 0xf2, 0x55, 0xff];
-const OFF_PIXEL_COLOR: [u8; 4] = [0xb5, 0x83, 0x16, 0xff];
+impl GraphicsMemory {
+    /// Number of XO-CHIP bitplanes
+    pub const PLANES: usize = 2;
 
-pub fn main_thread(
-    graphics_mem: Arc<RwLock<GraphicsMemory>>,
-    barrier: Arc<Barrier>,
-    inp_sender: Sender<(crate::input::Key, ElementState)>,
-) {
-    // safety: unwrap, as for any failures, we want to panic
+    pub fn new() -> Self {
+        tracing::info!("Initializing graphics memory");
+        Self {
+            pixels: vec![0; (SCREEN_SIZE.0 * SCREEN_SIZE.1) as usize],
+            hires: false,
+            selected_planes: 0b01,
+        }
+    }
 
-    let event_loop = EventLoop::new().unwrap(); // talk with the OS to create a window
-    event_loop.set_control_flow(ControlFlow::Poll); // maybe use waituntil(60hz/sth), but docs say to use poll
+    /// Width of the active resolution
+    pub fn width(&self) -> usize {
+        self.size().0 as usize
+    }
 
-    let mut app = App::new(graphics_mem, barrier, inp_sender);
+    /// Height of the active resolution
+    pub fn height(&self) -> usize {
+        self.size().1 as usize
+    }
 
-    event_loop.run_app(&mut app).unwrap();
+    fn size(&self) -> (Upixel, Upixel) {
+        if self.hires {
+            HIRES_SCREEN_SIZE
+        } else {
+            SCREEN_SIZE
+        }
+    }
 
-    // anything done here doesnt execute until the event loop is closed
-    // i.e., the app is closed.
-}
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
 
-struct App {
-    // window needs to be stored, as dropping it means closing the window
-    window: Option<Window>,
-    graphics_mem: Arc<RwLock<GraphicsMemory>>,
-    pixels: Option<Pixels>,
-    barrier: Arc<Barrier>,
-    inp_sender: Sender<(crate::input::Key, ElementState)>,
-}
+    /// Switches between 64x32 and 128x64, clears the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = vec![0; self.width() * self.height()];
+    }
 
-/// The complete memory assosciated to graphics
-pub struct GraphicsMemory(pub [bool; GraphicsMemory::TOTAL_PIXELS]);
-
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        info!("Window has been resumed/ initialized, probably initialized for the first time");
-
-        let window = event_loop
-            .create_window(
-                WindowAttributes::default()
-                    .with_title("CHIP-8 Emulator")
-                    .with_min_inner_size(PhysicalSize::new(WIDTH, HEIGHT))
-                    .with_resizable(true),
-            )
-            .expect("Failed to create window");
-        let surface_texture = SurfaceTexture::new(WIDTH, HEIGHT, &window);
-
-        self.pixels = Some(Pixels::new(SCREEN_SIZE.0, SCREEN_SIZE.1, surface_texture).unwrap());
-        self.window = Some(window);
-        self.barrier.wait();
-        let _ = self.render_mem();
-    }
-
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
-        use KeyCode::*;
-        match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(physical_size) => {
-                if let Some(pixels) = self.pixels.as_mut() {
-                    if let Err(e) = pixels.resize_surface(physical_size.width, physical_size.height)
-                    {
-                        // probably means the window is minimized
-                        tracing::error!("Failed to resize surface: {}", e);
-                        self.pixels = None;
-                    }
-                } else {
-                    tracing::warn!("Empty pixels, trying to re-initialize");
-                    let surface_texture =
-                        SurfaceTexture::new(WIDTH, HEIGHT, self.window.as_ref().unwrap());
-                    self.pixels =
-                        Some(Pixels::new(SCREEN_SIZE.0, SCREEN_SIZE.1, surface_texture).unwrap());
-                }
-            }
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
 
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(key),
-                        state,
-                        repeat: false,
-                        ..
-                    },
-                ..
-            } => match key {
-                KeyCode::Escape => tracing::info!("Escape key {:?}", state),
-                Numpad0 | Numpad1 | Numpad2 | Numpad3 | Numpad4 | Numpad5 | Numpad6 | Numpad7
-                | Numpad8 | Numpad9 | KeyA | KeyB | KeyC | KeyD | KeyE | KeyF => {
-                    if let Some(key) = crate::input::Key::from_key_code(key) {
-                        let _ = self.inp_sender.send((key, state));
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+    /// Only the lower 2 bits are used
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0b11;
     }
 
-    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: winit::event::StartCause) {
-        use winit::event::StartCause::*;
-        match cause {
-            // todo: emulate accurate timing/refresh rate
-            Poll => {
-                let _ = self.render_mem();
-                std::thread::sleep(std::time::Duration::from_micros(1_000_000 / 60));
-            }
+    /// The color of the pixel, one bit for each plane
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
 
-            Init => {}
-            _ => {}
+    /// Make a iterator over the pixels as registered in the graphics memory
+    pub fn iter(&self) -> crate::memory::MemoryIterator<'_, u8> {
+        crate::memory::MemoryIterator {
+            index: 0,
+            data_slice: &self.pixels,
+            max_index: self.pixels.len(),
         }
     }
-}
 
-impl App {
-    fn new(
-        graphics_mem: Arc<RwLock<GraphicsMemory>>,
-        barrier: Arc<Barrier>,
-        inp_sender: Sender<(crate::input::Key, ElementState)>,
-    ) -> Self {
-        Self {
-            window: None,
-            pixels: None,
-            graphics_mem,
-            barrier,
-            inp_sender,
-        }
+    /// Clears the selected planes
+    pub fn clear_screen(&mut self) {
+        let mask = !self.selected_planes;
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= mask);
     }
 
-    fn render_mem(&mut self) -> Result<(), Box<dyn std::error::Error + '_>> {
-        let pixels = self.pixels.as_mut().ok_or("Pixels not initialized")?;
-        let frame = pixels.frame_mut();
-        for (display_pixel, memory_value) in
-            std::iter::zip(frame.chunks_exact_mut(4), self.graphics_mem.read()?.iter())
-        {
-            #[rustfmt::skip]
-            let data = if *memory_value { ON_PIXEL_COLOR } else { OFF_PIXEL_COLOR };
-            display_pixel.copy_from_slice(&data);
-        }
+    /// Moves everything down by `rows`, the top gets blank
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
 
-        pixels.render()?;
-        Ok(())
+    /// Moves everything up by `rows`, the bottom gets blank
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
     }
-}
 
-impl GraphicsMemory {
-    const TOTAL_PIXELS: usize =
-        (crate::graphics::SCREEN_SIZE.0 * crate::graphics::SCREEN_SIZE.1) as usize;
+    /// Moves everything right by `columns`, the left gets blank
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
 
-    pub fn new() -> Self {
-        tracing::info!("Initializing graphics memory");
-        Self([false; Self::TOTAL_PIXELS])
+    /// Moves everything left by `columns`, the right gets blank
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
     }
 
-    /// Make a iterator over the pixels as registered in the graphics memory
-    pub fn iter<'it>(&self) -> crate::memory::MemoryIterator<bool> {
-        crate::memory::MemoryIterator {
-            index: 0,
-            data_slice: &self.0,
-            max_index: Self::TOTAL_PIXELS,
+    /// Scrolls only the selected planes, the others stay where they are
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mask = self.selected_planes;
+        let old = self.pixels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_y * width + src_x) as usize] & mask
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                *pixel = (*pixel & !mask) | moved;
+            }
         }
     }
 
-    pub fn clear_screen(&mut self) {
-        self.0 = [false; Self::TOTAL_PIXELS];
+    /// Number of bytes a sprite of `bytes_per_plane` takes, with the selected planes
+    pub fn sprite_len(&self, bytes_per_plane: usize) -> usize {
+        bytes_per_plane * self.selected_planes.count_ones() as usize
     }
 
+    /// Draws the sprite, clipping it at the edges of the screen,
+    /// or wrapping it around to the other side.
+    /// The starting position always wraps around.
+    ///
+    /// `sprite` holds the rows, each `width` pixels wide, 8 for the usual sprites,
+    /// 16 for the 16x16 sprites of SUPER-CHIP.
+    /// With both the planes selected, the sprite for the first plane is followed by
+    /// the one for the second plane.
     #[rustfmt::skip]
-    pub fn display_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
-        const MAX_X: usize = crate::graphics::SCREEN_SIZE.0 as usize;
-        const MAX_Y: usize = crate::graphics::SCREEN_SIZE.1 as usize;
-        let x = x as usize % MAX_X;
-        let y = y as usize % MAX_Y;
+    pub fn display_sprite(&mut self, x: u8, y: u8, sprite: &[u8], width: usize, clipping: bool) -> bool {
+        let (max_x, max_y) = (self.width(), self.height());
+        let x = x as usize % max_x;
+        let y = y as usize % max_y;
+        let bytes_per_row = width / 8;
+        let selected_planes = (0..Self::PLANES).filter(|plane| self.selected_planes & (1 << plane) != 0);
+        let Some(plane_len) = sprite.len().checked_div(self.selected_planes.count_ones() as usize) else {
+            return false;
+        };
 
         let mut collision = false;
-        for (y_off, sprite_byte) in sprite.iter().enumerate() {
-            let y = y + y_off;
-            if y >= MAX_Y { Self::report_out_of_screen(x, y); continue; }
-            for x_off in 0..8 {
-                let x = x + x_off;
-                if x >= MAX_X { Self::report_out_of_screen(x, y); continue; }
-                let pixel = &mut self.0[y*MAX_X + x];
-                let sprite_pixel = (sprite_byte >> (7 - x_off)) & 0x1 == 1;
-                collision |= *pixel && sprite_pixel;
-                *pixel ^= sprite_pixel;
+        for (plane, plane_sprite) in selected_planes.zip(sprite.chunks_exact(plane_len)) {
+            let plane_bit = 1 << plane;
+            for (y_off, sprite_row) in plane_sprite.chunks_exact(bytes_per_row).enumerate() {
+                let y = y + y_off;
+                if y >= max_y && clipping { Self::report_out_of_screen(x, y); continue; }
+                let y = y % max_y;
+                for x_off in 0..width {
+                    let x = x + x_off;
+                    if x >= max_x && clipping { Self::report_out_of_screen(x, y); continue; }
+                    let x = x % max_x;
+                    let pixel = &mut self.pixels[y*max_x + x];
+                    let sprite_pixel = (sprite_row[x_off / 8] >> (7 - x_off % 8)) & 0x1 == 1;
+                    if !sprite_pixel { continue; }
+                    collision |= *pixel & plane_bit != 0;
+                    *pixel ^= plane_bit;
+                }
             }
         }
         collision
@@ -202,27 +180,54 @@ impl GraphicsMemory {
     #[allow(unused)]
     /// For sake of testing, negate all the pixels
     pub fn negate(&mut self) {
-        for pixel in self.0.iter_mut() {
-            *pixel = !*pixel;
+        for pixel in self.pixels.iter_mut() {
+            *pixel ^= self.selected_planes;
         }
     }
 }
 
-/// an arbitary variable type holder, that I want to change
-/// incase any API to dependecy libraries changes
-type Upixel = u32;
+impl Default for GraphicsMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for GraphicsMemory {
+    fn save(&self, out: &mut StateWriter) {
+        out.bool(self.hires);
+        out.u8(self.selected_planes);
+        out.bytes(&self.pixels);
+    }
 
-use std::sync::{mpsc::Sender, Arc, Barrier, RwLock};
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut graphics = GraphicsMemory::new();
+        graphics.set_hires(input.bool()?);
+        graphics.select_planes(input.u8()?);
+        let pixels = input.bytes()?;
+        if pixels.len() != graphics.pixels.len() {
+            return Err(SaveStateError::Invalid("screen size"));
+        }
+        graphics.pixels.copy_from_slice(pixels);
+        Ok(graphics)
+    }
+}
 
-use winit::{
-    application::ApplicationHandler,
-    dpi::PhysicalSize,
-    event::{ElementState, KeyEvent, WindowEvent},
-    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowAttributes, WindowId},
-};
+impl Index<usize> for GraphicsMemory {
+    type Output = u8;
 
-use pixels::{Pixels, SurfaceTexture};
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.pixels[index]
+    }
+}
+impl IndexMut<usize> for GraphicsMemory {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.pixels[index]
+    }
+}
+
+/// an arbitary variable type holder, that I want to change
+/// incase any API to dependecy libraries changes
+pub type Upixel = u32;
 
-use tracing::info;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::ops::{Index, IndexMut};
@@ -3,7 +3,10 @@ pub struct InpuState {
     /// at the current moment.
     registry_stack: Vec<Key>,
 
-    rx: Receiver<(Key, ElementState)>,
+    /// Set when the CPU is stuck on a `FX0A`, waiting for a keypress
+    waiting: bool,
+    /// The first key that was pressed while the CPU was waiting
+    new_press: Option<Key>,
 }
 
 #[rustfmt::skip]
@@ -14,16 +17,20 @@ pub enum Key{
 }
 
 impl InpuState {
-    pub fn new(rx: Receiver<(Key, ElementState)>) -> Self {
+    pub fn new() -> Self {
         InpuState {
             registry_stack: Vec::with_capacity(16),
-            rx,
+            waiting: false,
+            new_press: None,
         }
     }
 
     /// Updates, according to the keypresses
-    fn update(&mut self, (key, state): (Key, ElementState)) {
-        if state.is_pressed() {
+    pub fn update(&mut self, key: Key, pressed: bool) {
+        if pressed {
+            if self.waiting && self.new_press.is_none() {
+                self.new_press = Some(key);
+            }
             self.registry_stack.push(key);
         } else {
             self.registry_stack.retain(|&k| k != key);
@@ -35,28 +42,16 @@ impl InpuState {
         }
     }
 
-    /// Fills the buffer with the keypresses that happend recently
-    pub fn receive_keys(&mut self) {
-        while let Ok(input) = self.rx.try_recv() {
-            self.update(input)
-        }
-    }
-
-    /// waits till a new press
-    /// and returns the key that was next fifo pressed
-    pub fn wait_for_key(&mut self) -> Key {
-        let current_pressed = self.registry_stack.first().cloned();
-        loop {
-            if let Ok(input) = self.rx.recv() {
-                self.update(input);
-                let new_press = self.registry_stack.first().cloned();
-                if let Some(_) = new_press {
-                    if new_press != current_pressed {
-                        return new_press.unwrap();
-                    }
-                }
-            }
+    /// Doesnt block, the CPU is expected to call this again till it gets a key.
+    ///
+    /// returns the key that was first pressed after the waiting started
+    pub fn wait_for_key(&mut self) -> Option<Key> {
+        self.waiting = true;
+        let key = self.new_press.take();
+        if key.is_some() {
+            self.waiting = false;
         }
+        key
     }
 
     pub fn is_pressed(&self, key: Key) -> bool {
@@ -64,29 +59,37 @@ impl InpuState {
     }
 }
 
-impl Key {
-    pub fn from_key_code(key:KeyCode) -> Option<Key> {
-        Some(match key {
-            KeyCode::Numpad0 => Key::Zero,
-            KeyCode::Numpad1 => Key::One,
-            KeyCode::Numpad2 => Key::Two,
-            KeyCode::Numpad3 => Key::Three,
-            KeyCode::Numpad4 => Key::Four,
-            KeyCode::Numpad5 => Key::Five,
-            KeyCode::Numpad6 => Key::Six,
-            KeyCode::Numpad7 => Key::Seven,
-            KeyCode::Numpad8 => Key::Eight,
-            KeyCode::Numpad9 => Key::Nine,
-            KeyCode::KeyA => Key::A,
-            KeyCode::KeyB => Key::B,
-            KeyCode::KeyC => Key::C,
-            KeyCode::KeyD => Key::D,
-            KeyCode::KeyE => Key::E,
-            KeyCode::KeyF => Key::F,
-            _ => {
-                tracing::error!("Unknown key: {:?}", key);
-                return None;
-            },
+impl Default for InpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for InpuState {
+    fn save(&self, out: &mut StateWriter) {
+        let keys: Vec<u8> = self.registry_stack.iter().map(|&key| key.into()).collect();
+        out.bytes(&keys);
+        out.bool(self.waiting);
+        // 0xFF for no key
+        out.u8(self.new_press.map_or(0xFF, u8::from));
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        let key = |value: u8| Key::try_from(value).map_err(|_| SaveStateError::Invalid("key"));
+        let registry_stack = input
+            .bytes()?
+            .iter()
+            .map(|&k| key(k))
+            .collect::<Result<_, _>>()?;
+        let waiting = input.bool()?;
+        let new_press = match input.u8()? {
+            0xFF => None,
+            value => Some(key(value)?),
+        };
+        Ok(InpuState {
+            registry_stack,
+            waiting,
+            new_press,
         })
     }
 }
@@ -114,17 +117,15 @@ impl TryFrom<u8> for Key {
             _ => {
                 tracing::error!("Unknown key: {:?}", value);
                 return Err(());
-            },
+            }
         })
     }
 }
 
-impl Into<u8> for Key{
-    fn into(self) -> u8 {
-        self as u8
+impl From<Key> for u8 {
+    fn from(key: Key) -> u8 {
+        key as u8
     }
 }
 
-use std::sync::mpsc::Receiver;
-
-use winit::{event::ElementState, keyboard::KeyCode};
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
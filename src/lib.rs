@@ -0,0 +1,43 @@
+//! The core of the emulator, without any window, sound or keyboard handling.
+//!
+//! Structure of the library:
+//! - [`Chip8`] is the whole machine, it owns the CPU and everything the CPU talks to:
+//!   the memory, the graphics memory, both timers, the XO-CHIP audio, the keypad
+//!   and the random numbers.
+//! - Nothing in here spawns threads or sleeps, the one embedding the machine
+//!   decides how fast it runs, by calling [`Chip8::step`] or [`Chip8::run_frame`].
+//! - The state is exposed through plain accessors, so any frontend can draw
+//!   the screen, beep, or dump the registers however it likes.
+//! - The whole machine can be snapshotted with [`Chip8::save_state`], see [`savestate`],
+//!   and [`rewind::Rewind`] keeps the last few seconds of those.
+//! - Opcodes are decoded into [`cpu::Instruction`], which [`disasm`] turns back into assembly,
+//!   and [`assembler`] builds roms out of Octo source.
+//!   [`analysis`] finds the control flow, subroutines and data of a rom.
+//! - [`symbols`] names the addresses of a rom, for the [`debugger`], [`trace`] and [`disasm`].
+//! - [`coverage`] marks the bytes a run executed, read and wrote, to find the code it never reached.
+//! - [`profile`] counts where a rom spends its instructions, by address, subroutine and opcode.
+//! - [`memview`] shows the memory as hex, marking what the [`debugger`] saw being read/written.
+
+pub mod analysis;
+pub mod assembler;
+pub mod audio;
+pub mod coverage;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod graphics;
+pub mod input;
+pub mod memory;
+pub mod memview;
+pub mod profile;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod savestate;
+pub mod symbols;
+pub mod timers;
+pub mod trace;
+
+mod chip8;
+
+pub use chip8::Chip8;
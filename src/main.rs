@@ -1,17 +1,13 @@
-//! Structure of the project:
-//! - any data that is meant to be used is made as a struct.
-//! The struct will be initialized in the main thread.
-//! (I actually wanted to initialized and locate it on the static/.data section,
-//!  but rust doesnt seem good with mutable statics)
-//! All those data, if needed, will be wrapped in some kind of std::sync lock.
-//! also, they shall be on the heap.
-
-mod cpu;
-mod graphics;
-mod input;
-mod memory;
-mod sound;
-mod timers;
+//! Structure of the binary:
+//! - the machine itself comes from the library, see [`chip_8_emulator::Chip8`].
+//!
+//! Everything runs on the main thread, in the event loop of the window.
+//! Each 60Hz frame runs a fixed number of instructions, ticks the timers once,
+//! queues the sound of the frame and draws the screen, so the same rom with the
+//! same inputs always behaves the same.
+//! The only other thread reads the stdin, for the commands of the debugger.
+
+mod frontend;
 
 fn main() {
     let args = Args::parse();
@@ -24,108 +20,327 @@ fn main() {
             .init(),
     };
 
-    let sync_barrier = Arc::new(Barrier::new(4));
+    let (rom, mut symbols) = load_rom(&args.rom_path);
+    load_symbols(&mut symbols, &args.rom_path, args.symbols.as_deref());
+    let mut quirks = Quirks::preset(args.quirks.into());
+    for (quirk, value) in &args.quirk {
+        if let Err(e) = quirks.set(quirk, value) {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    let mut chip8 = match args.quirks {
+        QuirkPreset::XoChip => Chip8::new_xo_chip(&rom),
+        _ => Chip8::new(&rom),
+    }
+    .expect("Failed to construct the CPU");
+    chip8.set_quirks(quirks);
+    chip8.set_instructions_per_frame(args.instructions_per_frame);
+    if let Some(seed) = args.seed {
+        chip8.set_seed(seed);
+    }
+    if let Some(path) = &args.load_state {
+        let loaded = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|state| chip8.load_state(&state).map_err(|e| e.to_string()));
+        if let Err(e) = loaded {
+            tracing::error!("Failed to load the state {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
 
-    let delay_timer = Arc::new(RwLock::new(timers::BaseTimer::new()));
+    let mut debugger = Debugger::new(!matches!(args.pauses, Pauses::None));
+    debugger.set_dump_memory(matches!(args.pauses, Pauses::WithMemory));
+    debugger.set_history_limit(args.history);
+    debugger.set_symbols(symbols);
+    if let Some(path) = &args.trace {
+        let file = std::fs::File::create(path).unwrap_or_else(|e| {
+            tracing::error!("Failed to create the trace {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        let mut trace = Trace::new(BufWriter::new(file));
+        for range in &args.trace_filter {
+            trace.add_filter(range.clone());
+        }
+        debugger.set_trace(trace);
+    }
+    if args.profile.is_some() {
+        debugger.set_profile(Profile::new());
+    }
 
-    // todo: graphics memory could be shared in other types
-    let graphics_mem = Arc::new(RwLock::new(graphics::GraphicsMemory::new()));
+    let gdb = args.gdb.map(|port| {
+        GdbStub::bind(port).unwrap_or_else(|e| {
+            tracing::error!("Failed to listen for gdb on port {}: {}", port, e);
+            debugger.flush_trace();
+            std::process::exit(1);
+        })
+    });
+    let save_slots = SaveSlots::new(&args.rom_path);
+    let rewind = Rewind::new((args.rewind * 60.) as usize);
+    let mut debugger =
+        frontend::window::main_thread(chip8, debugger, read_stdin(), gdb, save_slots, rewind);
+    if let (Some(path), Some(profile)) = (&args.profile, debugger.profile()) {
+        write_profile(path, profile, debugger.symbols());
+    }
+    if let Some(path) = &args.coverage {
+        write_coverage(path, &rom, &debugger);
+    }
 
-    let rom = std::fs::File::open(args.rom_path).expect("Unable to open the file");
-    let graphics_mem_cpu_cpy = Arc::clone(&graphics_mem);
-    let cpu_thread_blocker = Arc::clone(&sync_barrier);
-    let pauses = args.pauses;
-    let cpu_delay_timer = Arc::clone(&delay_timer);
-    let (tx, rx) = std::sync::mpsc::channel();
-    let (sound_tx, sound_rx) = std::sync::mpsc::channel();
+    debugger.flush_trace();
+    tracing::info!("Exiting main thread");
+    std::process::exit(0); // explicitly exit the program, so that the stdin thread
+                           // doesnt keep it alive
+}
 
-    // cpu thread
-    // todo: when cpu sneezes, the rest of the components should catch a cold
-    // or maybe not?
-    thread::Builder::new()
-        .name("CPU".to_string())
-        .spawn(move || {
-            let cpu_constructed = cpu::CPU::new(
-                rom,
-                graphics_mem_cpu_cpy,
-                cpu_delay_timer,
-                input::InpuState::new(rx),
-                sound_tx,
-            );
-            // no unblock before panicing the thread, else the  window becomes unresponsive
-            cpu_thread_blocker.wait();
-            tracing::info!("CPU thread started");
-            let mut cpu = cpu_constructed.expect("Failed to construct the CPU");
-            match pauses {
-                Pauses::None => cpu.run(),
-                Pauses::Simple => cpu.run_with_pauses(),
-                Pauses::WithMemory => cpu.run_with_pauses_dump_mem(),
-            }
-        })
-        .unwrap();
+/// The rom at `path`, assembling it first if it is Octo source,
+/// in which case the labels are its symbols
+fn load_rom(path: &Path) -> (Vec<u8>, Symbols) {
+    if path.extension().is_some_and(|extension| extension == "8o") {
+        let source = std::fs::read_to_string(path).expect("Unable to open the file");
+        return assembler::assemble_with_symbols(&source).unwrap_or_else(|e| {
+            tracing::error!("Failed to assemble {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+    }
+    (
+        std::fs::read(path).expect("Unable to open the file"),
+        Symbols::new(),
+    )
+}
 
-    let delay_timer_sync = Arc::clone(&sync_barrier);
-    // delay timer thread
-    thread::Builder::new()
-        .name("Delay Timer".to_string())
-        .spawn(move || {
-            delay_timer_sync.wait();
-            tracing::info!("Delay Timer thread started");
-            loop {
-                std::thread::sleep(std::time::Duration::from_micros(1_000_000 / 60)); // 60Hz
-                delay_timer.write().unwrap().decrement();
+/// Adds the symbols of `path`, or of the <ROM>.sym next to the rom if there is one
+fn load_symbols(symbols: &mut Symbols, rom_path: &Path, path: Option<&Path>) {
+    let default = rom_path.with_extension("sym");
+    let path = match path {
+        Some(path) => path,
+        None if default.is_file() => &default,
+        None => return,
+    };
+    let loaded = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Symbols::parse(&text).map_err(|e| e.to_string()));
+    match loaded {
+        Ok(loaded) => {
+            tracing::info!("Loaded {} symbols from {}", loaded.len(), path.display());
+            for (address, name) in loaded.iter() {
+                symbols.insert(address, name);
             }
-        })
-        .unwrap();
+        }
+        Err(e) => {
+            tracing::error!("Failed to load the symbols {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let sound_sync = Arc::clone(&sync_barrier);
-    // sound thread
+/// Writes the report to `path`, and the folded stacks next to it with a .folded extension
+fn write_profile(path: &Path, profile: &Profile, symbols: &Symbols) {
+    let folded = path.with_extension("folded");
+    for (path, text) in [
+        (path, profile.report(symbols)),
+        (&folded, profile.folded(symbols)),
+    ] {
+        match std::fs::write(path, text) {
+            Ok(()) => tracing::info!("Wrote the profile {}", path.display()),
+            Err(e) => tracing::error!("Failed to write the profile {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Writes the JSON to `path`, and the annotated listing next to it with a .lst extension
+fn write_coverage(path: &Path, rom: &[u8], debugger: &Debugger) {
+    let coverage = debugger.coverage();
+    let listing = path.with_extension("lst");
+    for (path, text) in [
+        (path, coverage.json(rom)),
+        (&listing, coverage.listing(rom, Syntax::Octo, debugger.symbols())),
+    ] {
+        match std::fs::write(path, text) {
+            Ok(()) => tracing::info!("Wrote the coverage {}", path.display()),
+            Err(e) => tracing::error!("Failed to write the coverage {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Every line of the stdin, each one is a command for the debugger
+fn read_stdin() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
     thread::Builder::new()
-        .name("Sound".to_string())
+        .name("Stdin".to_string())
         .spawn(move || {
-            sound::main_thread(sound_rx, sound_sync);
+            for line in stdin().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
         })
         .unwrap();
-
-    graphics::main_thread(graphics_mem, sync_barrier, tx);
-
-    tracing::info!("Exiting main thread");
-    std::process::exit(0); // explicitly exit the program, so that other threads(cpu->GPUMem)
-                           // can also be closed
+    receiver
 }
 
 #[derive(clap::Parser)]
 struct Args {
-    /// Path to the rom file
-    rom_path: String,
-
-    /// Run with [p]auses
-    /// the emulator will wait for input after each cycle
-    /// 
-    /// All dump information is sent to the stdout.
-    /// The debug level must be atleast emulator-only
+    /// Path to the rom file, a .8o file is assembled as Octo source
+    rom_path: PathBuf,
+
+    /// Start with [p]auses
+    /// the emulator starts stopped in the debugger, before the first instruction
+    ///
+    /// The debugger takes its commands from the stdin, `help` lists them.
     #[clap(short, long, default_value = "none")]
     pauses: Pauses,
 
+    /// Number of instructions executed in each 60Hz frame
+    ///
+    /// The timers are ticked once per frame, so this is the speed of the CPU.
+    #[clap(short, long, default_value_t = Chip8::DEFAULT_INSTRUCTIONS_PER_FRAME)]
+    instructions_per_frame: u32,
+
+    /// Seed for the random numbers of `CXNN`
+    ///
+    /// The same seed and the same inputs always play out the same.
+    /// Without one, a random seed is picked, and logged.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Starts from a save state, instead of the start of the rom
+    ///
+    /// The states saved with Shift+F1..F4 are next to the rom, as <ROM>.state1..4
+    #[clap(long, value_name = "PATH")]
+    load_state: Option<PathBuf>,
+
+    /// Seconds that can be rewound, by holding Backspace
+    ///
+    /// 0 turns rewinding off
+    #[clap(long, value_name = "SECONDS", default_value_t = 10.)]
+    rewind: f32,
+
+    /// Instructions the debugger keeps, to step back over them with `reverse-step`
+    ///
+    /// 0 turns stepping back off
+    #[clap(long, value_name = "INSTRUCTIONS", default_value_t = DEFAULT_HISTORY_LIMIT)]
+    history: usize,
+
+    /// Writes a line per executed instruction, with the registers before it
+    ///
+    /// `trace-diff` finds where two of these, or traces of other emulators, part ways.
+    #[clap(long, value_name = "PATH")]
+    trace: Option<PathBuf>,
+
+    /// Only traces the instructions at ADDR, or from ADDR to END, can be given multiple times
+    #[clap(long, value_name = "ADDR[-END]", value_parser = parse_address_range, requires = "trace")]
+    trace_filter: Vec<RangeInclusive<usize>>,
+
+    /// Counts the executed instructions, and writes where they went when the emulator closes
+    ///
+    /// The report lists the hottest addresses, the subroutines and the opcodes.
+    /// The call stacks go next to it with a .folded extension, for flamegraph.pl or speedscope.
+    #[clap(long, value_name = "PATH")]
+    profile: Option<PathBuf>,
+
+    /// Writes what the run executed, read and wrote as JSON, when the emulator closes
+    ///
+    /// The disassembly of the rom goes next to it with a .lst extension,
+    /// with how many times each instruction ran, ##### for never. F8 shows it as a heatmap.
+    #[clap(long, value_name = "PATH")]
+    coverage: Option<PathBuf>,
+
+    /// Names the addresses of the rom in the debugger and the trace
+    ///
+    /// A line per symbol, `ADDR NAME`, as written by `chip8-asm --symbols`.
+    /// By default <ROM>.sym is loaded if there is one, and the labels of a .8o source are always known.
+    #[clap(long, value_name = "PATH")]
+    symbols: Option<PathBuf>,
+
+    /// Lets gdb, or an editor speaking its remote protocol, debug the rom
+    ///
+    /// Listens on 127.0.0.1:<PORT>, `target remote :<PORT>` connects to it.
+    #[clap(long, value_name = "PORT")]
+    gdb: Option<u16>,
+
     /// Debug level
     #[clap(short, long, default_value = "emulator-only")]
     debug: Debug,
+
+    /// The interpreter whose quirks the rom expects
+    #[clap(short, long, default_value = "cosmac-vip")]
+    quirks: QuirkPreset,
+
+    /// Overrides a single quirk of the preset, can be given multiple times
+    ///
+    /// vf-reset, shift, jump and clipping take true/false,
+    /// load-store takes x-plus-one, x or unchanged, stack-depth the most nested calls.
+    /// e.g. `--quirk shift=false --quirk load-store=unchanged`
+    #[clap(long, value_name = "QUIRK=VALUE", value_parser = parse_quirk)]
+    quirk: Vec<(String, String)>,
+}
+
+fn parse_quirk(arg: &str) -> Result<(String, String), String> {
+    let (quirk, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected QUIRK=VALUE, got {arg}"))?;
+    // check it here, so that clap reports it along with the usage
+    Quirks::default().set(quirk, value)?;
+    Ok((quirk.to_string(), value.to_string()))
+}
+
+/// `0x200` or `0x200-0x2FF`, decimal or hex
+fn parse_address_range(arg: &str) -> Result<RangeInclusive<usize>, String> {
+    let address = |text: &str| {
+        let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => text.parse(),
+        };
+        parsed.map_err(|_| format!("`{text}` is not an address"))
+    };
+    match arg.split_once('-') {
+        Some((start, end)) => Ok(address(start)?..=address(end)?),
+        None => address(arg).map(|address| address..=address),
+    }
 }
 
 #[derive(Clone, clap::ValueEnum)]
 enum Pauses {
     /// Run at almost native speed
     None,
-    /// Waits for input after each CPU cycle
+    /// Starts stopped, an empty line steps a single instruction
     Simple,
-    /// Dumps memory after each cycle.
+    /// Also dumps memory whenever it stops.
     /// shortword/alias: mem
     #[clap(alias = "mem")]
     WithMemory,
 }
 
-#[derive(Clone, clap::ValueEnum)]
-#[derive(Debug)]
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum QuirkPreset {
+    /// The original interpreter
+    CosmacVip,
+    /// The HP48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.0
+    #[clap(name = "schip-1.0", alias = "schip10")]
+    SuperChip10,
+    /// SUPER-CHIP 1.1
+    #[clap(name = "schip-1.1", alias = "schip")]
+    SuperChip11,
+    /// Octo's XO-CHIP, also gives the machine 64KiB of memory
+    XoChip,
+}
+
+impl From<QuirkPreset> for Preset {
+    fn from(preset: QuirkPreset) -> Self {
+        match preset {
+            QuirkPreset::CosmacVip => Preset::CosmacVip,
+            QuirkPreset::Chip48 => Preset::Chip48,
+            QuirkPreset::SuperChip10 => Preset::SuperChip10,
+            QuirkPreset::SuperChip11 => Preset::SuperChip11,
+            QuirkPreset::XoChip => Preset::XoChip,
+        }
+    }
+}
+
+#[derive(Clone, clap::ValueEnum, Debug)]
 enum Debug {
     /// Donot print any debug messages
     None,
@@ -139,8 +354,23 @@ enum Debug {
 }
 
 use std::{
-    sync::{Arc, Barrier, RwLock},
+    io::{stdin, BufWriter},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
     thread,
 };
 
+use chip_8_emulator::{
+    assembler,
+    cpu::Syntax,
+    debugger::{Debugger, DEFAULT_HISTORY_LIMIT},
+    quirks::{Preset, Quirks},
+    profile::Profile,
+    rewind::Rewind,
+    symbols::Symbols,
+    trace::Trace,
+    Chip8,
+};
 use clap::Parser;
+use frontend::{gdb::GdbStub, save_slots::SaveSlots};
@@ -4,13 +4,19 @@
 /// 2**12 possible addresses
 /// addressable from 0x000 to 0xFFF
 ///
+/// or 64KiB for XO-CHIP, addressable from 0x0000 to 0xFFFF
+///
 /// ## Note
 /// Donot allocate it on stack, as it itself is 4KiB
-///     nah, I did.
+///     nah, I did. (it is on the heap now, 64KiB is a bit too much)
 #[derive(Debug)]
-pub struct Memory([u8; 4096]);
+pub struct Memory(Vec<u8>);
 
 impl Memory {
+    /// Total number of bytes in the memory
+    pub const SIZE: usize = 4096;
+    /// Total number of bytes in the memory of XO-CHIP
+    pub const XO_CHIP_SIZE: usize = 0x10000;
     /// The memory address where the instructions would start
     pub const INSTRUCTIONS_START_ADDRESS: usize = 0x200;
     pub const DIGITS_FONTS_START_ADDRESS: usize = 0x000;
@@ -32,26 +38,96 @@ impl Memory {
         0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
         0xF0, 0x80, 0xF0, 0x80, 0x80, // F
     ];
+    /// SUPER-CHIP's 8x10 digits, right after the small ones
+    pub const BIG_DIGITS_FONTS_START_ADDRESS: usize =
+        Self::DIGITS_FONTS_START_ADDRESS + Self::DIGITS_FONTS.len();
+    #[rustfmt::skip]
+    const BIG_DIGITS_FONTS: [u8; 160] = [
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+    ];
 
-    pub fn load_instructions(mut file: std::fs::File) -> Result<Memory, &'static str> {
-        let mut data = [0; 4096];
+    /// `size` is the total number of bytes in the memory,
+    /// either [`Memory::SIZE`] or [`Memory::XO_CHIP_SIZE`]
+    pub fn load_instructions(rom: &[u8], size: usize) -> Result<Memory, &'static str> {
+        let mut data = vec![0; size];
         data[Self::DIGITS_FONTS_START_ADDRESS
             ..Self::DIGITS_FONTS_START_ADDRESS + Self::DIGITS_FONTS.len()]
             .copy_from_slice(&Self::DIGITS_FONTS);
+        data[Self::BIG_DIGITS_FONTS_START_ADDRESS
+            ..Self::BIG_DIGITS_FONTS_START_ADDRESS + Self::BIG_DIGITS_FONTS.len()]
+            .copy_from_slice(&Self::BIG_DIGITS_FONTS);
         tracing::info!("Loading instructions into memory");
-        let file_size = file.metadata().unwrap().len() as usize;
-        if file_size > 4096 - Self::INSTRUCTIONS_START_ADDRESS {
+        if rom.len() > size - Self::INSTRUCTIONS_START_ADDRESS {
             return Err("Instructions are too large to fit in memory");
         }
-        file.read(&mut data[Self::INSTRUCTIONS_START_ADDRESS..])
-            .expect("Unable to read the file");
+        data[Self::INSTRUCTIONS_START_ADDRESS..Self::INSTRUCTIONS_START_ADDRESS + rom.len()]
+            .copy_from_slice(rom);
         Ok(Memory(data))
     }
 
+    /// Total number of bytes in the memory
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The memory is never empty, there are atleast the fonts
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Gets the address of digit's font in memory
     pub const fn get_digit_address(digit: u8) -> usize {
         Self::DIGITS_FONTS_START_ADDRESS + (digit as usize * 5)
     }
+
+    /// Gets the address of digit's big font in memory
+    pub const fn get_big_digit_address(digit: u8) -> usize {
+        Self::BIG_DIGITS_FONTS_START_ADDRESS + (digit as usize * 10)
+    }
+}
+
+impl Snapshot for Memory {
+    fn save(&self, out: &mut StateWriter) {
+        out.bytes(&self.0);
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        let data = input.bytes()?;
+        if data.len() != Self::SIZE && data.len() != Self::XO_CHIP_SIZE {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        Ok(Memory(data.to_vec()))
+    }
+}
+
+/// Memory touched by an instruction, other than fetching it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub range: std::ops::Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    /// `DXYN`, `FX65`, `F002` and XO-CHIP's `5XY3`
+    Read,
+    /// `FX33`, `FX55` and XO-CHIP's `5XY2`
+    Write,
 }
 
 /// A generic iterator for the memory structs
@@ -99,22 +175,5 @@ impl IndexMut<usize> for Memory {
     }
 }
 
-impl Index<usize> for GraphicsMemory {
-    type Output = bool;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
-    }
-}
-impl IndexMut<usize> for GraphicsMemory {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index]
-    }
-}
-
-use std::{
-    io::Read,
-    ops::{Index, IndexMut},
-};
-
-use crate::graphics::GraphicsMemory;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::ops::{Index, IndexMut};
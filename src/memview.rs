@@ -0,0 +1,108 @@
+//! Hex views of the memory, for the debugger and the overlay,
+//! along with which bytes were read/written lately.
+
+/// An access older than this many instructions isnt highlighted anymore
+pub const RECENT_CYCLES: u64 = 256;
+
+/// The bytes in a line of [`hex`]
+pub const BYTES_PER_LINE: usize = 16;
+
+/// When every byte of the memory was last read and written
+#[derive(Debug, Default)]
+pub struct AccessHistory {
+    /// The [`Chip8::cycles`] after the access, 0 for never
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl AccessHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers what the last step of the machine read/wrote
+    pub fn record(&mut self, machine: &Chip8) {
+        let cycle = machine.cycles();
+        for access in machine.memory_accesses() {
+            let cycles = match access.kind {
+                AccessKind::Read => &mut self.reads,
+                AccessKind::Write => &mut self.writes,
+            };
+            if cycles.len() < access.range.end {
+                cycles.resize(access.range.end, 0);
+            }
+            cycles[access.range.clone()].fill(cycle);
+        }
+    }
+
+    /// How `address` was touched in the last [`RECENT_CYCLES`] before `now`,
+    /// a write wins over a read
+    pub fn recent(&self, address: usize, now: u64) -> Option<AccessKind> {
+        // after a reset or a rewind, the history can be ahead of the machine
+        let is_recent = |cycles: &[u64]| {
+            cycles
+                .get(address)
+                .is_some_and(|&at| at != 0 && at <= now && now - at < RECENT_CYCLES)
+        };
+        if is_recent(&self.writes) {
+            Some(AccessKind::Write)
+        } else if is_recent(&self.reads) {
+            Some(AccessKind::Read)
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.reads.clear();
+        self.writes.clear();
+    }
+}
+
+/// [`BYTES_PER_LINE`] bytes per line, each one prefixed with what `mark` returns for it.
+///
+/// Repeats of the line above without any marks are collapsed into a `*`, like `hexdump` does.
+pub fn hex(memory: &Memory, range: Range<usize>, mark: &dyn Fn(usize) -> char) -> String {
+    let end = range.end.min(memory.len());
+    let mut lines = Vec::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut collapsed = false;
+    for start in (range.start..end).step_by(BYTES_PER_LINE) {
+        let addresses = start..(start + BYTES_PER_LINE).min(end);
+        let bytes = &memory[addresses.clone()];
+        let marked = addresses.clone().any(|address| mark(address) != ' ');
+        if !marked && previous == Some(bytes) {
+            if !collapsed {
+                lines.push("*".to_string());
+                collapsed = true;
+            }
+            continue;
+        }
+        previous = Some(bytes);
+        collapsed = false;
+        let cells: String = addresses
+            .map(|address| format!("{}{:02X}", mark(address), memory[address]))
+            .collect();
+        lines.push(format!("0x{start:04X}:{cells}"));
+    }
+    lines.join("\n")
+}
+
+/// Every byte as a row of an 8 pixel wide sprite, `#` for the pixels that are on
+pub fn sprites(memory: &Memory, range: Range<usize>) -> String {
+    let end = range.end.min(memory.len());
+    (range.start..end)
+        .map(|address| {
+            let byte = memory[address];
+            let row: String = (0..8)
+                .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                .collect();
+            format!("0x{address:04X}: {byte:02X} {row}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+use crate::memory::{AccessKind, Memory};
+use crate::Chip8;
+use std::ops::Range;
@@ -0,0 +1,157 @@
+//! Where a rom spends its instructions.
+//!
+//! [`Profile::record`] counts every instruction by its address, its [`Instruction::pattern`]
+//! and the calls the machine was in when it ran. [`Profile::report`] sorts those into hot spots,
+//! subroutines and opcodes, and [`Profile::folded`] writes the call stacks the way
+//! flamegraph.pl and speedscope read them, `main;update;draw_player 1234`.
+
+/// How many of the hottest addresses the report lists
+pub const HOT_SPOTS: usize = 32;
+
+/// Counts of the executed instructions
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Executions of every address
+    addresses: HashMap<usize, Hits>,
+    /// Executions of every [`Instruction::pattern`]
+    patterns: HashMap<&'static str, u64>,
+    /// Executions under every call stack, the callees outermost first,
+    /// `None` where the call site doesnt hold a call anymore
+    stacks: HashMap<Vec<Option<usize>>, u64>,
+    total: u64,
+}
+
+#[derive(Debug)]
+struct Hits {
+    count: u64,
+    /// The opcode last executed there, self modifying code can change it
+    opcode: u16,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the instruction the machine is about to execute
+    pub fn record(&mut self, machine: &Chip8) {
+        let pc = machine.program_counter();
+        let memory = machine.memory();
+        let Some(opcode) = (pc + 1 < memory.len())
+            .then(|| u16::from_be_bytes([memory[pc], memory[pc + 1]]))
+        else {
+            return;
+        };
+        self.total += 1;
+        self.addresses
+            .entry(pc)
+            .and_modify(|hits| {
+                hits.count += 1;
+                hits.opcode = opcode;
+            })
+            .or_insert(Hits { count: 1, opcode });
+        let pattern = Instruction::decode(opcode).map_or("????", |i| i.pattern());
+        *self.patterns.entry(pattern).or_default() += 1;
+        let stack: Vec<_> = call_stack(machine)
+            .into_iter()
+            .map(|frame| frame.callee)
+            .collect();
+        *self.stacks.entry(stack).or_default() += 1;
+    }
+
+    /// Number of instructions counted
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The hottest addresses, the subroutines and the opcodes, each sorted by their counts
+    pub fn report(&self, symbols: &Symbols) -> String {
+        let percent = |count: u64| 100. * count as f64 / self.total.max(1) as f64;
+        let mut out = format!("{} instructions\n", self.total);
+
+        out.push_str("\nHot spots\n    count      %  address\n");
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by_key(|&(&address, hits)| (Reverse(hits.count), address));
+        for (&address, hits) in addresses.into_iter().take(HOT_SPOTS) {
+            let assembly = match Instruction::decode(hits.opcode) {
+                Ok(instruction) => {
+                    let label = |target: u16| symbols.name(target as usize).map(str::to_string);
+                    instruction.format(Syntax::Classic, None, &label)
+                }
+                Err(_) => format!("DW 0x{:04X}", hits.opcode),
+            };
+            let at = match symbols.locate(address) {
+                Some(symbol) => format!("0x{address:04X} ({symbol})"),
+                None => format!("0x{address:04X}"),
+            };
+            out.push_str(&format!(
+                "{:>9} {:>5.1}%  {at:<32} {assembly}\n",
+                hits.count,
+                percent(hits.count)
+            ));
+        }
+
+        // self is spent in the subroutine itself, total also counts what it called
+        let mut subroutines: HashMap<String, (u64, u64)> = HashMap::new();
+        for (stack, &count) in &self.stacks {
+            let names = stack_names(stack, symbols);
+            subroutines.entry(names.last().unwrap().clone()).or_default().0 += count;
+            let mut seen = HashSet::new();
+            for name in names {
+                if seen.insert(name.clone()) {
+                    subroutines.entry(name).or_default().1 += count;
+                }
+            }
+        }
+        let mut subroutines: Vec<_> = subroutines.into_iter().collect();
+        subroutines.sort_by(|(a, (a_self, _)), (b, (b_self, _))| b_self.cmp(a_self).then(a.cmp(b)));
+        out.push_str("\nSubroutines\n     self      %     total      %  subroutine\n");
+        for (name, (self_count, total)) in subroutines {
+            out.push_str(&format!(
+                "{self_count:>9} {:>5.1}% {total:>9} {:>5.1}%  {name}\n",
+                percent(self_count),
+                percent(total)
+            ));
+        }
+
+        let mut patterns: Vec<_> = self.patterns.iter().collect();
+        patterns.sort_by_key(|&(&pattern, &count)| (Reverse(count), pattern));
+        out.push_str("\nOpcodes\n    count      %  opcode\n");
+        for (pattern, &count) in patterns {
+            out.push_str(&format!("{count:>9} {:>5.1}%  {pattern}\n", percent(count)));
+        }
+        out
+    }
+
+    /// A line per call stack, the names split by `;` and followed by the count
+    pub fn folded(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| format!("{} {count}", stack_names(stack, symbols).join(";")))
+            .collect();
+        lines.sort();
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+}
+
+/// The names of the subroutines of a stack, starting with the rom itself as `main`.
+/// Those without a symbol are named like [`crate::disasm`] labels them, `sub_22E`
+fn stack_names(stack: &[Option<usize>], symbols: &Symbols) -> Vec<String> {
+    let root = symbols.name(ORIGIN).unwrap_or("main").to_string();
+    let callees = stack.iter().map(|&callee| match callee {
+        Some(callee) => symbols
+            .name(callee)
+            .map_or_else(|| format!("sub_{callee:03X}"), str::to_string),
+        None => "unknown".to_string(),
+    });
+    std::iter::once(root).chain(callees).collect()
+}
+
+use crate::cpu::{Instruction, Syntax};
+use crate::debugger::call_stack;
+use crate::disasm::ORIGIN;
+use crate::symbols::Symbols;
+use crate::Chip8;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
@@ -0,0 +1,167 @@
+/// The behaviours that differ between the CHIP-8 interpreters.
+///
+/// Roms are usually written against one interpreter, and break on the others,
+/// see https://github.com/Timendus/chip8-test-suite#quirks-test for the details.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0
+    pub vf_reset: bool,
+    /// How much `FX55`/`FX65` move the I register
+    pub load_store: LoadStore,
+    /// `8XY6`/`8XYE` shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// `BXNN` jumps to XNN + VX, instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// Sprites going out of the screen are clipped, instead of wrapping around
+    pub clipping: bool,
+    /// Most nested `2NNN` calls, one more is a stack overflow.
+    /// 16 on most interpreters, but as low as 12 on some
+    pub stack_depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadStore {
+    /// I is left pointing after the last register, `I += X + 1`
+    IncrementByXPlusOne,
+    /// The off by one of CHIP-48, `I += X`
+    IncrementByX,
+    /// I is not changed
+    Unchanged,
+}
+
+/// The interpreters that have a known set of quirks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    CosmacVip,
+    Chip48,
+    SuperChip10,
+    SuperChip11,
+    XoChip,
+}
+
+impl Quirks {
+    /// The original interpreter, this is also what the emulator always did
+    pub const COSMAC_VIP: Quirks = Quirks {
+        vf_reset: true,
+        load_store: LoadStore::IncrementByXPlusOne,
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        clipping: true,
+        stack_depth: 16,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        vf_reset: false,
+        load_store: LoadStore::IncrementByX,
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        clipping: true,
+        stack_depth: 16,
+    };
+
+    pub const SUPER_CHIP_1_0: Quirks = Quirks::CHIP_48;
+
+    pub const SUPER_CHIP_1_1: Quirks = Quirks {
+        load_store: LoadStore::Unchanged,
+        ..Quirks::CHIP_48
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        load_store: LoadStore::IncrementByXPlusOne,
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        clipping: false,
+        stack_depth: 16,
+    };
+
+    pub const fn preset(preset: Preset) -> Quirks {
+        match preset {
+            Preset::CosmacVip => Self::COSMAC_VIP,
+            Preset::Chip48 => Self::CHIP_48,
+            Preset::SuperChip10 => Self::SUPER_CHIP_1_0,
+            Preset::SuperChip11 => Self::SUPER_CHIP_1_1,
+            Preset::XoChip => Self::XO_CHIP,
+        }
+    }
+
+    /// Overrides a single quirk by its name, as in `shift=false` or `load-store=x`
+    ///
+    /// The names are `vf-reset`, `load-store`, `shift`, `jump`, `clipping` and `stack-depth`.
+    /// `load-store` takes `x-plus-one`, `x` or `unchanged`, `stack-depth` a number from 1 to 255,
+    /// the rest take a boolean.
+    pub fn set(&mut self, quirk: &str, value: &str) -> Result<(), String> {
+        let flag = || match value {
+            "true" | "on" | "yes" | "1" => Ok(true),
+            "false" | "off" | "no" | "0" => Ok(false),
+            _ => Err(format!(
+                "Expected a boolean for the quirk {quirk}, got {value}"
+            )),
+        };
+        match quirk {
+            "vf-reset" => self.vf_reset = flag()?,
+            "shift" => self.shift_uses_vy = flag()?,
+            "jump" => self.jump_uses_vx = flag()?,
+            "clipping" => self.clipping = flag()?,
+            "stack-depth" => {
+                self.stack_depth = value
+                    .parse()
+                    .ok()
+                    .filter(|depth| (1..=255).contains(depth))
+                    .ok_or(format!("Expected a stack depth from 1 to 255, got {value}"))?
+            }
+            "load-store" => {
+                self.load_store = match value {
+                    "x-plus-one" => LoadStore::IncrementByXPlusOne,
+                    "x" => LoadStore::IncrementByX,
+                    "unchanged" => LoadStore::Unchanged,
+                    _ => return Err(format!("Unknown load-store behaviour: {value}")),
+                }
+            }
+            _ => return Err(format!("Unknown quirk: {quirk}")),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::COSMAC_VIP
+    }
+}
+
+impl Snapshot for Quirks {
+    fn save(&self, out: &mut StateWriter) {
+        out.bool(self.vf_reset);
+        out.u8(match self.load_store {
+            LoadStore::IncrementByXPlusOne => 0,
+            LoadStore::IncrementByX => 1,
+            LoadStore::Unchanged => 2,
+        });
+        out.bool(self.shift_uses_vy);
+        out.bool(self.jump_uses_vx);
+        out.bool(self.clipping);
+        out.u8(self.stack_depth as u8);
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Quirks {
+            vf_reset: input.bool()?,
+            load_store: match input.u8()? {
+                0 => LoadStore::IncrementByXPlusOne,
+                1 => LoadStore::IncrementByX,
+                2 => LoadStore::Unchanged,
+                _ => return Err(SaveStateError::Invalid("load-store quirk")),
+            },
+            shift_uses_vy: input.bool()?,
+            jump_uses_vx: input.bool()?,
+            clipping: input.bool()?,
+            stack_depth: match input.u8()? {
+                0 => return Err(SaveStateError::Invalid("stack depth")),
+                depth => depth as usize,
+            },
+        })
+    }
+}
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
@@ -0,0 +1,241 @@
+/// The last few seconds of the machine, one save state per frame.
+///
+/// Only the newest state is kept whole, every older one is kept as the difference
+/// to the state after it, which is mostly zeros from one frame to the next,
+/// so those get squeezed out.
+pub struct Rewind {
+    /// Maximum number of frames that can be rewound
+    capacity: usize,
+    /// The newest state, as given by [`Chip8::save_state`]
+    latest: Option<Vec<u8>>,
+    /// Each one turns a state into the one before it, the newest is at the back
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// Keeps upto `capacity` frames, 0 keeps nothing
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Number of frames that can be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Records the state of the machine, meant to be called after every frame
+    pub fn push(&mut self, machine: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = machine.save_state();
+        if let Some(previous) = self.latest.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(Delta::between(&state, &previous));
+        }
+        self.latest = Some(state);
+    }
+
+    /// Takes the machine back by one frame, returns false if there is nothing left to rewind
+    pub fn pop(&mut self, machine: &mut Chip8) -> bool {
+        let (Some(latest), Some(delta)) = (self.latest.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+        delta.apply(latest);
+        machine
+            .load_state(latest)
+            .expect("Rewound states are always made by the same machine");
+        true
+    }
+}
+
+/// The XOR of two states, as runs of changed bytes after runs of unchanged ones
+struct Delta {
+    /// Length of the state this turns into
+    len: usize,
+    /// Pairs of (unchanged bytes to skip, changed bytes), followed by the changed bytes,
+    /// the numbers as LEB128
+    runs: Vec<u8>,
+}
+
+impl Delta {
+    /// The delta that turns `from` into `to`
+    fn between(from: &[u8], to: &[u8]) -> Self {
+        let xor = |index: usize| from.get(index).unwrap_or(&0) ^ to.get(index).unwrap_or(&0);
+        let total = from.len().max(to.len());
+        let mut runs = Vec::new();
+        let mut index = 0;
+        while index < total {
+            let start = index;
+            while index < total && xor(index) == 0 {
+                index += 1;
+            }
+            if index == total {
+                break;
+            }
+            let skipped = index - start;
+            let changed_start = index;
+            while index < total && xor(index) != 0 {
+                index += 1;
+            }
+            write_number(&mut runs, skipped);
+            write_number(&mut runs, index - changed_start);
+            runs.extend((changed_start..index).map(xor));
+        }
+        Delta {
+            len: to.len(),
+            runs,
+        }
+    }
+
+    fn apply(&self, state: &mut Vec<u8>) {
+        if state.len() < self.len {
+            state.resize(self.len, 0);
+        }
+        let mut runs = self.runs.iter().copied();
+        let mut index = 0;
+        while let Some(skipped) = read_number(&mut runs) {
+            index += skipped;
+            let changed = read_number(&mut runs).expect("runs come in pairs");
+            if state.len() < index + changed {
+                state.resize(index + changed, 0);
+            }
+            for (byte, xor) in state[index..index + changed].iter_mut().zip(&mut runs) {
+                *byte ^= xor;
+            }
+            index += changed;
+        }
+        state.truncate(self.len);
+    }
+}
+
+fn write_number(out: &mut Vec<u8>, mut number: usize) {
+    loop {
+        let byte = (number & 0x7F) as u8;
+        number >>= 7;
+        if number == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_number(bytes: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut number = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next()?;
+        number |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(number);
+        }
+        shift += 7;
+    }
+}
+
+use crate::Chip8;
+use std::collections::VecDeque;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `from` with `changes` applied, as (index, byte)
+    fn changed(from: &[u8], changes: &[(usize, u8)]) -> Vec<u8> {
+        let mut to = from.to_vec();
+        for &(index, byte) in changes {
+            to[index] = byte;
+        }
+        to
+    }
+
+    fn round_trip(from: &[u8], to: &[u8]) {
+        let mut state = from.to_vec();
+        Delta::between(from, to).apply(&mut state);
+        assert_eq!(state, to);
+    }
+
+    #[test]
+    fn a_delta_restores_the_exact_bytes() {
+        let from: Vec<u8> = (0..1000).map(|n| n as u8).collect();
+        // runs longer than 127 bytes take more than a byte of LEB128
+        let mut to = changed(&from, &[(0, 0xFF), (5, 0), (999, 1)]);
+        to[300..500].iter_mut().for_each(|byte| *byte = !*byte);
+        round_trip(&from, &to);
+        round_trip(&to, &from);
+    }
+
+    #[test]
+    fn the_same_state_gives_an_empty_delta() {
+        let state = vec![1, 2, 3, 4];
+        assert!(Delta::between(&state, &state).runs.is_empty());
+        round_trip(&state, &state);
+    }
+
+    #[test]
+    fn states_of_different_lengths() {
+        let short = vec![1, 2, 3];
+        let long: Vec<u8> = (0..400).map(|n| (n * 7) as u8).collect();
+        round_trip(&short, &long);
+        round_trip(&long, &short);
+        round_trip(&[], &long);
+        round_trip(&long, &[]);
+        // only the length differs
+        round_trip(&[1, 2, 0, 0], &[1, 2]);
+        round_trip(&[1, 2], &[1, 2, 0, 0]);
+    }
+
+    #[test]
+    fn numbers_round_trip_through_leb128() {
+        for number in [0, 1, 127, 128, 300, 16383, 16384, usize::MAX] {
+            let mut out = Vec::new();
+            write_number(&mut out, number);
+            assert_eq!(read_number(&mut out.into_iter()), Some(number));
+        }
+        assert_eq!(read_number(&mut [0x80].into_iter()), None);
+    }
+
+    /// v0 += 1, jump 0x200, a different state on every step
+    const ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn the_oldest_frames_are_dropped_at_capacity() {
+        let mut machine = Chip8::new(&ROM).unwrap();
+        let mut rewind = Rewind::new(3);
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            states.push(machine.save_state());
+            rewind.push(&machine);
+            machine.step().unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+        for state in states[1..4].iter().rev() {
+            assert!(rewind.pop(&mut machine));
+            assert_eq!(&machine.save_state(), state);
+        }
+        assert!(!rewind.pop(&mut machine));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn no_capacity_keeps_nothing() {
+        let mut machine = Chip8::new(&ROM).unwrap();
+        let mut rewind = Rewind::new(0);
+        rewind.push(&machine);
+        machine.step().unwrap();
+        rewind.push(&machine);
+        assert!(rewind.is_empty());
+        assert!(!rewind.pop(&mut machine));
+    }
+}
@@ -0,0 +1,112 @@
+/// Where the random numbers of `CXNN` come from.
+///
+/// Owned by the CPU, so that a run can be repeated exactly, given the same seed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rng {
+    /// xorshift64*, the same seed always gives the same numbers
+    Seeded { seed: u64, state: u64 },
+    /// Hands out the given numbers in order, looping around, meant for tests
+    Scripted { numbers: Vec<u8>, next: usize },
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Rng::Seeded {
+            seed,
+            state: Self::initial_state(seed),
+        }
+    }
+
+    /// Seeded from the OS, the seed is logged so the run can still be repeated
+    pub fn from_entropy() -> Self {
+        let seed = rand::random();
+        tracing::info!("Random numbers seeded with {}", seed);
+        Self::seeded(seed)
+    }
+
+    /// `numbers` must not be empty
+    pub fn scripted(numbers: Vec<u8>) -> Self {
+        assert!(!numbers.is_empty(), "A scripted rng needs some numbers");
+        Rng::Scripted { numbers, next: 0 }
+    }
+
+    /// The seed it started with, scripted ones dont have any
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Rng::Seeded { seed, .. } => Some(*seed),
+            Rng::Scripted { .. } => None,
+        }
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        match self {
+            Rng::Seeded { state, .. } => {
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            Rng::Scripted { numbers, next } => {
+                let number = numbers[*next];
+                *next = (*next + 1) % numbers.len();
+                number
+            }
+        }
+    }
+
+    /// Starts the sequence all over again
+    pub fn restart(&mut self) {
+        match self {
+            Rng::Seeded { seed, state } => *state = Self::initial_state(*seed),
+            Rng::Scripted { next, .. } => *next = 0,
+        }
+    }
+
+    /// splitmix64 of the seed, xorshift gets stuck on a zero state
+    fn initial_state(seed: u64) -> u64 {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)).max(1)
+    }
+}
+
+impl Snapshot for Rng {
+    fn save(&self, out: &mut StateWriter) {
+        match self {
+            Rng::Seeded { seed, state } => {
+                out.u8(0);
+                out.u64(*seed);
+                out.u64(*state);
+            }
+            Rng::Scripted { numbers, next } => {
+                out.u8(1);
+                out.bytes(numbers);
+                out.u32(*next as u32);
+            }
+        }
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        match input.u8()? {
+            0 => {
+                let seed = input.u64()?;
+                match input.u64()? {
+                    0 => Err(SaveStateError::Invalid("random number state")),
+                    state => Ok(Rng::Seeded { seed, state }),
+                }
+            }
+            1 => {
+                let numbers = input.bytes()?.to_vec();
+                let next = input.u32()? as usize;
+                if next >= numbers.len() {
+                    return Err(SaveStateError::Invalid("scripted random numbers"));
+                }
+                Ok(Rng::Scripted { numbers, next })
+            }
+            _ => Err(SaveStateError::Invalid("kind of random numbers")),
+        }
+    }
+}
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
@@ -0,0 +1,252 @@
+//! The binary format of the save states.
+//!
+//! A save state starts with [`MAGIC`] and the [`VERSION`] of the format,
+//! followed by every part of the machine, one after the other, in the order
+//! [`crate::Chip8::save_state`] writes them. Numbers are little endian,
+//! variable sized things are prefixed with their length as a `u32`.
+//!
+//! Bump the version on any change to the layout, older states then get refused,
+//! instead of being loaded into garbage.
+
+/// The first bytes of every save state
+pub const MAGIC: &[u8; 4] = b"CH8S";
+/// Version of the layout
+pub const VERSION: u16 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    /// Doesnt start with [`MAGIC`]
+    NotASaveState,
+    /// Written by a different version of the emulator
+    UnsupportedVersion(u16),
+    /// Ended before the whole machine was read
+    Truncated,
+    /// Some value that the machine can never hold
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "Not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "Save state version {version} is not supported, expected {VERSION}"
+            ),
+            SaveStateError::Truncated => write!(f, "The save state is truncated"),
+            SaveStateError::Invalid(what) => write!(f, "Invalid save state: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// A part of the machine that goes into the save states
+pub(crate) trait Snapshot: Sized {
+    fn save(&self, out: &mut StateWriter);
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError>;
+}
+
+pub(crate) struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    /// Starts with the magic and the version
+    pub fn new() -> Self {
+        let mut writer = StateWriter(MAGIC.to_vec());
+        writer.u16(VERSION);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length prefixed
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the magic and the version
+    pub fn new(data: &'a [u8]) -> Result<Self, SaveStateError> {
+        let mut reader = StateReader { data };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        match reader.u16()? {
+            VERSION => Ok(reader),
+            version => Err(SaveStateError::UnsupportedVersion(version)),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < len {
+            return Err(SaveStateError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("boolean")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Anything left after the machine is a sign of a broken save state
+    pub fn finish(self) -> Result<(), SaveStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::Invalid("trailing bytes"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8;
+
+    /// v0 := 5, v1 := random 0xFF, i := hex v0, sprite v0 v1 5, v0 += 1, jump 0x202
+    const ROM: [u8; 12] = [0x60, 0x05, 0xC1, 0xFF, 0xF0, 0x29, 0xD0, 0x15, 0x70, 0x01, 0x12, 0x02];
+
+    fn running() -> Chip8 {
+        let mut machine = Chip8::new(&ROM).unwrap();
+        machine.set_seed(7);
+        for _ in 0..10 {
+            machine.step().unwrap();
+        }
+        machine
+    }
+
+    #[test]
+    fn a_running_machine_round_trips() {
+        let mut machine = running();
+        let state = machine.save_state();
+        let mut loaded = Chip8::new(&[0x00, 0xE0]).unwrap();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.save_state(), state);
+        assert_eq!(loaded.program_counter(), machine.program_counter());
+        assert_eq!(loaded.registers(), machine.registers());
+
+        // and it carries on the same, random numbers included
+        for _ in 0..10 {
+            machine.step().unwrap();
+            loaded.step().unwrap();
+        }
+        assert_eq!(loaded.save_state(), machine.save_state());
+    }
+
+    #[test]
+    fn truncated_states_are_refused_and_change_nothing() {
+        let state = running().save_state();
+        let mut machine = Chip8::new(&ROM).unwrap();
+        let before = machine.save_state();
+        let header = MAGIC.len() + 2;
+        for len in (header..state.len()).step_by(97).chain([state.len() - 1]) {
+            assert_eq!(machine.load_state(&state[..len]), Err(SaveStateError::Truncated), "{len} bytes");
+        }
+        assert_eq!(machine.load_state(&state[..2]), Err(SaveStateError::NotASaveState));
+        assert_eq!(machine.save_state(), before);
+    }
+
+    #[test]
+    fn trailing_bytes_are_refused() {
+        let mut state = running().save_state();
+        state.push(0);
+        let error = Chip8::new(&ROM).unwrap().load_state(&state);
+        assert_eq!(error, Err(SaveStateError::Invalid("trailing bytes")));
+    }
+
+    #[test]
+    fn the_magic_and_the_version_are_checked() {
+        let state = running().save_state();
+        let mut machine = Chip8::new(&ROM).unwrap();
+
+        let mut wrong_magic = state.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(machine.load_state(&wrong_magic), Err(SaveStateError::NotASaveState));
+
+        let mut wrong_version = state;
+        wrong_version[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            machine.load_state(&wrong_version),
+            Err(SaveStateError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn a_stack_deeper_than_the_quirk_is_refused() {
+        // calls itself, one more return address on every step
+        let mut machine = Chip8::new(&[0x22, 0x00]).unwrap();
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        let mut quirks = *machine.quirks();
+        quirks.stack_depth = 2;
+        machine.set_quirks(quirks);
+        let state = machine.save_state();
+        let error = Chip8::new(&ROM).unwrap().load_state(&state);
+        assert_eq!(error, Err(SaveStateError::Invalid("stack depth")));
+
+        quirks.stack_depth = 3;
+        machine.set_quirks(quirks);
+        let state = machine.save_state();
+        assert_eq!(Chip8::new(&ROM).unwrap().load_state(&state), Ok(()));
+    }
+}
@@ -0,0 +1,228 @@
+//! Names of the addresses in a rom, for the debugger, the traces and the disassembly.
+//!
+//! A symbol file has a symbol per line, its address and its name in either order,
+//! optionally with a `:` or `=` in between, so all of these work:
+//! ```text
+//! 0x022A draw_player
+//! 022A draw_player
+//! : draw_player 0x22A
+//! draw_player = 0x22A
+//! ```
+//! A name that is also a hex number, like `beef`, needs its address written with the `0x`.
+//! Everything after a `#` or a `;` is a comment.
+//! [`crate::assembler::assemble_with_symbols`] gives the labels of an Octo source.
+
+/// Why a symbol file couldnt be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolError {
+    /// 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+/// Addresses and their names, both ways
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    names: BTreeMap<usize, String>,
+    addresses: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a symbol file, see the [module](self) for the format
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| SymbolError {
+                line: index + 1,
+                message,
+            };
+            let code = line.split(['#', ';']).next().unwrap_or_default();
+            let words: Vec<_> = code
+                .split_whitespace()
+                .filter(|&word| word != ":" && word != "=")
+                .collect();
+            let (address, name) = match words[..] {
+                [] => continue,
+                [first, second] => match (number(first), number(second)) {
+                    (Some((address, _)), None) => (address, second),
+                    (None, Some((address, _))) => (address, first),
+                    // an explicit 0x wins over something that only looks like hex, e.g. `beef`
+                    (Some((address, true)), Some((_, false))) => (address, second),
+                    (Some((_, false)), Some((address, true))) => (address, first),
+                    (None, None) => return Err(error(format!("No address in `{}`", line.trim()))),
+                    (Some(_), Some(_)) => {
+                        return Err(error(format!(
+                            "Either of `{first}` and `{second}` could be the address, write it with a 0x"
+                        )))
+                    }
+                },
+                _ => return Err(error(format!("Expected an address and a name, not `{}`", line.trim()))),
+            };
+            symbols.insert(address, name);
+        }
+        Ok(symbols)
+    }
+
+    /// Names `address`. An address with more than one name shows the first one,
+    /// the others only work the other way around, in [`Symbols::address`]
+    pub fn insert(&mut self, address: usize, name: impl Into<String>) {
+        let name = name.into();
+        if let Some(old) = self.addresses.insert(name.clone(), address) {
+            if old != address && self.names.get(&old) == Some(&name) {
+                self.names.remove(&old);
+            }
+        }
+        self.names.entry(address).or_insert(name);
+    }
+
+    /// The name of exactly `address`
+    pub fn name(&self, address: usize) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.addresses.get(name).copied()
+    }
+
+    /// The name of `address`, or `name+0x4` if the closest symbol before it is 4 bytes back
+    pub fn locate(&self, address: usize) -> Option<String> {
+        let (&start, name) = self.names.range(..=address).next_back()?;
+        Some(match address - start {
+            0 => name.clone(),
+            offset => format!("{name}+0x{offset:X}"),
+        })
+    }
+
+    /// How many names there are
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Every name, in the order of the addresses, the name that is shown first
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        let mut all: Vec<_> = self
+            .addresses
+            .iter()
+            .map(|(name, &address)| (address, name.as_str()))
+            .collect();
+        all.sort_by_key(|&(address, name)| (address, self.name(address) != Some(name), name));
+        all.into_iter()
+    }
+}
+
+/// Writes them as a symbol file, that [`Symbols::parse`] reads back
+impl std::fmt::Display for Symbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (address, name) in self.iter() {
+            writeln!(f, "0x{address:04X} {name}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An address, and whether it had a `0x`
+fn number(word: &str) -> Option<(usize, bool)> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok().map(|n| (n, true)),
+        None => usize::from_str_radix(word, 16).ok().map(|n| (n, false)),
+    }
+}
+
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<(usize, String)> {
+        let symbols = Symbols::parse(text).unwrap();
+        symbols.iter().map(|(address, name)| (address, name.to_string())).collect()
+    }
+
+    fn error(text: &str) -> String {
+        Symbols::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn both_orders() {
+        let expected = [(0x22A, "draw".to_string())];
+        for text in [
+            "0x022A draw",
+            "022A draw",
+            "22a draw",
+            "draw 0x22A",
+            "draw 22A",
+            ": draw 0x22A",
+            "draw = 0x22A",
+            "0x22A : draw",
+        ] {
+            assert_eq!(parse(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn hex_words_as_names() {
+        // the explicit 0x is the address, on either side
+        assert_eq!(parse("0x200 beef"), [(0x200, "beef".to_string())]);
+        assert_eq!(parse("beef 0x200"), [(0x200, "beef".to_string())]);
+        assert_eq!(parse("add 0X300"), [(0x300, "add".to_string())]);
+        // without it, either word could be the address
+        assert_eq!(
+            error("face cafe"),
+            "line 1: Either of `face` and `cafe` could be the address, write it with a 0x"
+        );
+        assert!(Symbols::parse("200 beef").is_err());
+        assert!(Symbols::parse("0x200 0x300").is_err());
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let text = "
+            # the player
+            0x200 main ; where it starts
+
+            0x22A draw   # the sprite routine
+            ;0x300 gone
+        ";
+        assert_eq!(
+            parse(text),
+            [(0x200, "main".to_string()), (0x22A, "draw".to_string())]
+        );
+    }
+
+    #[test]
+    fn broken_lines_have_their_line() {
+        assert_eq!(error("0x200 main\nplayer sprite"), "line 2: No address in `player sprite`");
+        assert_eq!(
+            error("\n\n0x200 main extra"),
+            "line 3: Expected an address and a name, not `0x200 main extra`"
+        );
+        assert!(Symbols::parse("main").is_err());
+    }
+
+    #[test]
+    fn aliases_and_locating() {
+        let symbols = Symbols::parse("0x200 main\n0x200 start\n0x220 draw").unwrap();
+        assert_eq!(symbols.name(0x200), Some("main"));
+        assert_eq!(symbols.address("start"), Some(0x200));
+        assert_eq!(symbols.locate(0x224).as_deref(), Some("draw+0x4"));
+        assert_eq!(symbols.locate(0x1FF), None);
+        assert_eq!(Symbols::parse(&symbols.to_string()), Ok(symbols));
+    }
+}
@@ -4,7 +4,7 @@ pub struct BaseTimer {
 }
 impl BaseTimer {
     pub fn new() -> Self {
-        BaseTimer {count: 0}
+        BaseTimer { count: 0 }
     }
 
     pub fn set_timer(&mut self, time: u8) {
@@ -18,4 +18,21 @@ impl BaseTimer {
     pub fn read(&self) -> u8 {
         self.count
     }
-}
\ No newline at end of file
+}
+impl Default for BaseTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for BaseTimer {
+    fn save(&self, out: &mut StateWriter) {
+        out.u8(self.count);
+    }
+
+    fn load(input: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(BaseTimer { count: input.u8()? })
+    }
+}
+
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
@@ -0,0 +1,213 @@
+//! One line per executed instruction, to compare a run against another emulator.
+//!
+//! A line is the state right before the instruction, as `key=value` fields,
+//! followed by the assembly of the instruction after a `;`, e.g.
+//! ```text
+//! cycle=42 pc=0208 op=D015 v=0A,00,...,00 i=0228 sp=0 dt=00 st=00 ; DRW V0, V1, 5
+//! ```
+//! Everything after the `;` is ignored by [`diff`], so traces from other emulators only
+//! need to match the fields. That is also where the symbols go, e.g.
+//! `... ; CALL draw_player` and `... ; LD V0, 0x05 <draw_player+0x4>`.
+
+/// Writes the trace of a machine, see [`line`]
+pub struct Trace {
+    out: Box<dyn Write>,
+    /// Only the instructions in these get written, all of them if empty
+    filters: Vec<RangeInclusive<usize>>,
+}
+
+impl Trace {
+    pub fn new(out: impl Write + 'static) -> Self {
+        Trace {
+            out: Box::new(out),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Only write the instructions in `range`, can be called multiple times
+    pub fn add_filter(&mut self, range: RangeInclusive<usize>) {
+        self.filters.push(range);
+    }
+
+    /// Writes the line of the instruction the machine is about to execute,
+    /// unless it is filtered out
+    pub fn record(&mut self, machine: &Chip8, symbols: &Symbols) -> io::Result<()> {
+        let pc = machine.program_counter();
+        if !self.filters.is_empty() && !self.filters.iter().any(|range| range.contains(&pc)) {
+            return Ok(());
+        }
+        writeln!(self.out, "{}", line(machine, symbols))
+    }
+
+    /// Writes out whatever is still buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The trace line of the instruction the machine is about to execute,
+/// naming its address and its target with `symbols`
+pub fn line(machine: &Chip8, symbols: &Symbols) -> String {
+    let pc = machine.program_counter();
+    let memory = machine.memory();
+    let word = |address: usize| {
+        (address + 1 < memory.len())
+            .then(|| u16::from_be_bytes([memory[address], memory[address + 1]]))
+    };
+    let label = |target: u16| symbols.name(target as usize).map(str::to_string);
+    let (opcode, mut assembly) = match word(pc) {
+        Some(opcode) => {
+            let assembly = match Instruction::decode(opcode) {
+                Ok(instruction) => instruction.format(Syntax::Classic, word(pc + 2), &label),
+                Err(_) => "??".to_string(),
+            };
+            (format!("{opcode:04X}"), assembly)
+        }
+        None => ("----".to_string(), "??".to_string()),
+    };
+    if let Some(symbol) = symbols.locate(pc) {
+        assembly = format!("{assembly} <{symbol}>");
+    }
+    let registers: Vec<_> = machine
+        .registers()
+        .iter()
+        .map(|v| format!("{v:02X}"))
+        .collect();
+    format!(
+        "cycle={} pc={pc:04X} op={opcode} v={} i={:04X} sp={} dt={:02X} st={:02X} ; {assembly}",
+        machine.cycles(),
+        registers.join(","),
+        machine.i_register(),
+        machine.stack().len(),
+        machine.delay_timer(),
+        machine.sound_timer(),
+    )
+}
+
+/// Where two traces first disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// 1 based
+    pub line: usize,
+    /// The lines of both traces, `None` where a trace has already ended
+    pub left: Option<String>,
+    pub right: Option<String>,
+    /// The fields that differ, empty if a trace ended
+    pub fields: Vec<String>,
+}
+
+/// The first line where the traces disagree, on any field that both of them have
+pub fn diff(left: &str, right: &str) -> Option<Divergence> {
+    let mut left_lines = left.lines();
+    let mut right_lines = right.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (left, right) = match (left_lines.next(), right_lines.next()) {
+            (None, None) => return None,
+            (Some(left), Some(right)) => (left, right),
+            (left, right) => {
+                return Some(Divergence {
+                    line,
+                    left: left.map(str::to_string),
+                    right: right.map(str::to_string),
+                    fields: Vec::new(),
+                })
+            }
+        };
+        let right_fields = fields(right);
+        let differing: Vec<_> = fields(left)
+            .into_iter()
+            .filter(|(key, value)| right_fields.iter().any(|(k, v)| k == key && v != value))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        if !differing.is_empty() {
+            return Some(Divergence {
+                line,
+                left: Some(left.to_string()),
+                right: Some(right.to_string()),
+                fields: differing,
+            });
+        }
+    }
+}
+
+/// The `key=value` fields before the `;`, the values are compared case insensitively
+fn fields(line: &str) -> Vec<(&str, String)> {
+    let fields = line.split(';').next().unwrap_or_default();
+    fields
+        .split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .map(|(key, value)| (key, value.to_ascii_uppercase()))
+        .collect()
+}
+
+use crate::cpu::{Instruction, Syntax};
+use crate::symbols::Symbols;
+use crate::Chip8;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "\
+cycle=1 pc=0200 op=6005 v=00,00 i=0000 sp=0 ; LD V0, 0x05
+cycle=2 pc=0202 op=A228 v=05,00 i=0000 sp=0 ; LD I, 0x228
+cycle=3 pc=0204 op=D015 v=05,00 i=0228 sp=0 ; DRW V0, V1, 5
+";
+
+    #[test]
+    fn identical_traces() {
+        assert_eq!(diff(TRACE, TRACE), None);
+        assert_eq!(diff("", ""), None);
+        // the values are compared case insensitively, and whats after the `;` doesnt count
+        let other = TRACE.to_lowercase().replace("DRW V0, V1, 5", "drw v0, v1, 5 <main+0x4>");
+        assert_eq!(diff(TRACE, &other), None);
+    }
+
+    #[test]
+    fn a_register_differs() {
+        let other = TRACE.replace("v=05,00 i=0000", "v=05,01 i=0000");
+        let divergence = diff(TRACE, &other).unwrap();
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.fields, ["v"]);
+        assert_eq!(divergence.left.as_deref(), TRACE.lines().nth(1));
+        assert_eq!(divergence.right.as_deref(), other.lines().nth(1));
+    }
+
+    #[test]
+    fn several_fields_differ() {
+        let other = TRACE.replace("pc=0204 op=D015", "pc=0206 op=D016");
+        assert_eq!(diff(TRACE, &other).unwrap().fields, ["pc", "op"]);
+    }
+
+    #[test]
+    fn traces_of_different_lengths() {
+        let shorter: String = TRACE.lines().take(2).map(|line| format!("{line}\n")).collect();
+        assert_eq!(
+            diff(TRACE, &shorter),
+            Some(Divergence {
+                line: 3,
+                left: TRACE.lines().nth(2).map(str::to_string),
+                right: None,
+                fields: Vec::new(),
+            })
+        );
+        let divergence = diff("", TRACE).unwrap();
+        assert_eq!((divergence.line, divergence.left), (1, None));
+    }
+
+    #[test]
+    fn only_the_fields_of_both_traces_are_compared() {
+        // a trace without the registers, as a different emulator may write
+        let other = TRACE.replace(" v=05,00", "").replace(" v=00,00", "");
+        assert_eq!(diff(TRACE, &other), None);
+        let other = other.replace("i=0228", "i=0229");
+        assert_eq!(diff(TRACE, &other).unwrap().fields, ["i"]);
+        // lines without any fields cant disagree
+        assert_eq!(diff("garbage\nmore", "cycle=1\ncycle=2"), None);
+        assert_eq!(diff("pc 0200", "pc=0202"), None);
+    }
+}